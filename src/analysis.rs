@@ -0,0 +1,291 @@
+//! Semantic checks over a single condlist. Spans of the returned diagnostics
+//! are relative to the condlist source.
+
+use std::collections::BTreeMap;
+
+use crate::lint::Diagnostic;
+use crate::logic::{Conjunction, Literal, chance};
+use crate::order::{guards, impure};
+use crate::parser::{Ast, Block, Slice, Statement};
+
+/// Reports conditions that can never hold, blocks repeated in the same
+/// condition and chances made useless by a lower one.
+pub fn check_conditions(ast: &Ast) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+
+    for statement in ast.statements() {
+        let blocks = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+        let mut seen = BTreeMap::new();
+        let mut lowest: Option<(u32, usize)> = None;
+
+        for (ix, block) in blocks.iter().enumerate() {
+            let span = block.span();
+            let text = ast.format_block(block);
+
+            if let Some(x) = chance(ast, block) {
+                if x == 0 {
+                    out.push(Diagnostic::warning(
+                        span,
+                        "unsatisfiable-condition",
+                        "`~0` never passes".to_owned(),
+                    ));
+                } else if x >= 100 {
+                    out.push(Diagnostic::warning(
+                        span,
+                        "subsumed-check",
+                        format!("`{}` always passes", text),
+                    ));
+                } else if let Some((other, _)) = lowest.filter(|(other, _)| *other <= x) {
+                    out.push(Diagnostic::warning(
+                        span,
+                        "subsumed-check",
+                        format!("`{}` is implied by `~{}`, the roll is shared", text, other),
+                    ));
+                } else if let Some((other, other_ix)) = lowest {
+                    out.push(Diagnostic::warning(
+                        blocks[other_ix].span(),
+                        "subsumed-check",
+                        format!("`~{}` is implied by `{}`, the roll is shared", other, text),
+                    ));
+                }
+                if x > 0 && lowest.is_none_or(|(other, _)| x < other) {
+                    lowest = Some((x, ix));
+                }
+                continue;
+            }
+
+            let Some(literal) = Literal::from_block(ast, block) else {
+                continue;
+            };
+            match seen.get(&literal.atom) {
+                Some((value, _)) if *value == literal.value => out.push(Diagnostic::warning(
+                    span,
+                    "duplicate-block",
+                    format!("`{}` is already checked in this condition", text),
+                )),
+                Some((_, other)) => out.push(Diagnostic::error(
+                    span,
+                    "unsatisfiable-condition",
+                    format!(
+                        "`{}` contradicts `{}`, the condition never holds",
+                        text,
+                        ast.format_block(&blocks[*other])
+                    ),
+                )),
+                None => {
+                    seen.insert(literal.atom, (literal.value, ix));
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Reports statements that are never chosen because an earlier statement
+/// holds whenever they do, e.g. `walker@2` in `{+x} walker@1, {+x +y} walker@2`.
+/// Statements with unsatisfiable conditions are left to [`check_conditions`].
+pub fn check_reachability(ast: &Ast) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+    let conditions = ast
+        .statements()
+        .iter()
+        .map(|x| Conjunction::from_statement(ast, x))
+        .collect::<Vec<_>>();
+
+    for (j, later) in conditions.iter().enumerate() {
+        if later.contradictory {
+            continue;
+        }
+        let Some(i) = (0..j).find(|i| later.implies(&conditions[*i])) else {
+            continue;
+        };
+        let statement = &ast.statements()[i];
+        let Some(span) = statement_span(ast, &ast.statements()[j]) else {
+            continue;
+        };
+        let earlier = ast.format_statement(statement);
+        out.push(if conditions[i].is_always() {
+            Diagnostic::warning(
+                span,
+                "unreachable-statement",
+                format!("Never reached, `{}` is always chosen first", earlier),
+            )
+        } else {
+            Diagnostic::warning(
+                span,
+                "shadowed-statement",
+                format!("Shadowed by `{}`, which holds whenever this does", earlier),
+            )
+        });
+    }
+
+    out
+}
+
+/// Reports conditions with side effects, per the catalog, placed after a
+/// `~N` or info check. Blocks stop being checked at the first failing one, so
+/// whether the side effects happen depends on that check.
+pub fn check_side_effects(ast: &Ast) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+
+    for statement in ast.statements() {
+        let blocks = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+        for (ix, block) in blocks.iter().enumerate() {
+            if !impure(ast, block) {
+                continue;
+            }
+            let guard = guards(statement, ix)
+                .iter()
+                .find(|x| matches!(x, Block::Chance { .. } | Block::InfoPortion { .. }));
+            if let Some(guard) = guard {
+                out.push(Diagnostic::warning(
+                    block.span(),
+                    "side-effect-order",
+                    format!(
+                        "`{}` has side effects that only happen when `{}` passes",
+                        ast.format_block(block),
+                        ast.format_block(guard)
+                    ),
+                ));
+            }
+        }
+    }
+
+    out
+}
+
+/// From the first to the last block or output of a statement, with the
+/// surrounding `{` and `%` if any. The parser does not record their positions.
+fn statement_span(ast: &Ast, statement: &Statement) -> Option<Slice> {
+    let conditions = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+    let effects = statement.effects().map(|x| x.blocks()).unwrap_or(&[]);
+    let spans = conditions
+        .iter()
+        .chain(effects)
+        .map(|x| x.span())
+        .chain(statement.val().copied())
+        .collect::<Vec<_>>();
+    let src = ast.source().as_bytes();
+    let mut start = spans.iter().map(|x| x.index()).min()?;
+    let mut end = spans.iter().map(|x| x.end()).max()?;
+    if start > 0 && src[start - 1] == b'{' {
+        start -= 1;
+    }
+    if src.get(end) == Some(&b'%') {
+        end += 1;
+    }
+    Some(Slice::new(start, end - start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(src: &str) -> Vec<(&'static str, String)> {
+        let ast = Ast::from(src).unwrap();
+        check_conditions(&ast)
+            .into_iter()
+            .map(|x| (x.code, x.span.as_str(src).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn clean() {
+        assert_eq!(check("{+a -b =f(1) !f(2) ~30} X, {+a} Y %+a%"), vec![]);
+    }
+
+    #[test]
+    fn contradictions() {
+        assert_eq!(
+            check("{+a -a} X, {!f =f} Y, {~0} Z"),
+            vec![
+                ("unsatisfiable-condition", "-a".to_owned()),
+                ("unsatisfiable-condition", "=f".to_owned()),
+                ("unsatisfiable-condition", "~0".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        assert_eq!(
+            check("{+a +a =f(x) =f(x)} X"),
+            vec![
+                ("duplicate-block", "+a".to_owned()),
+                ("duplicate-block", "=f(x)".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn subsumed() {
+        assert_eq!(
+            check("{~30 ~50} X, {~50 ~30} Y, {~100} Z"),
+            vec![
+                ("subsumed-check", "~50".to_owned()),
+                ("subsumed-check", "~50".to_owned()),
+                ("subsumed-check", "~100".to_owned()),
+            ]
+        );
+    }
+
+    fn reachability(src: &str) -> Vec<(&'static str, String)> {
+        let ast = Ast::from(src).unwrap();
+        check_reachability(&ast)
+            .into_iter()
+            .map(|x| (x.code, x.span.as_str(src).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn unreachable() {
+        assert_eq!(
+            reachability("walker@1, {+x} walker@2"),
+            vec![("unreachable-statement", "{+x} walker@2".to_owned())]
+        );
+        assert_eq!(
+            reachability("{} walker@1, walker@2 %+a%"),
+            vec![("unreachable-statement", "walker@2 %+a%".to_owned())]
+        );
+    }
+
+    #[test]
+    fn shadowed() {
+        assert_eq!(
+            reachability("{+x =f(a)} walker@1, {=f(a) +x -y} walker@2, {+x} walker@3"),
+            vec![("shadowed-statement", "{=f(a) +x -y} walker@2".to_owned())]
+        );
+        assert_eq!(
+            reachability("{~30} walker@1, {+x ~10} walker@2"),
+            vec![("shadowed-statement", "{+x ~10} walker@2".to_owned())]
+        );
+    }
+
+    #[test]
+    fn side_effects() {
+        let src = "{~30 =random_choice(50)} X, {=random_choice(50) +a} Y, {!see_actor =is_day} Z";
+        let ast = Ast::from(src).unwrap();
+        let found = check_side_effects(&ast)
+            .into_iter()
+            .map(|x| (x.span.as_str(src).to_owned(), x.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![(
+                "=random_choice(50)".to_owned(),
+                "`=random_choice(50)` has side effects that only happen when `~30` passes"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn reachable() {
+        assert_eq!(
+            reachability("{+x} walker@1, {-x} walker@2, {~10} walker@3, {+x -x} walker@4, nil"),
+            vec![]
+        );
+        assert_eq!(reachability("{~10} walker@1, {~30} walker@2"), vec![]);
+    }
+}
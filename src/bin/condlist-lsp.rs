@@ -0,0 +1,446 @@
+//! Language server for condlists in LTX files, over stdio. The features live
+//! in `condlists_demystified::ide`, this only speaks the protocol.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use condlists_demystified::document::Document;
+use condlists_demystified::ide::{self, LineIndex, SymbolKind};
+use condlists_demystified::lint::Severity;
+use condlists_demystified::ltx::Ltx;
+use condlists_demystified::parser::{Slice, TokenKind};
+use condlists_demystified::tree::{SourceFile, Tree};
+use condlists_demystified::xref::InfoIndex;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, DidSaveTextDocument,
+    LogMessage, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, Formatting, GotoDefinition, HoverRequest, Request as _, SemanticTokensFullRequest,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionResponse, CompletionTextEdit,
+    Diagnostic, DiagnosticSeverity, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, InitializeParams, Location, LogMessageParams, MarkupContent,
+    MarkupKind, MessageType, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    SemanticToken, SemanticTokenType, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, TextDocumentSyncSaveOptions, TextEdit, Url,
+};
+
+/// Legend of semantic tokens, indexed by [`token_type`].
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::OPERATOR,
+];
+
+/// Standard token types, so that any theme colours condlists.
+fn token_type(kind: TokenKind) -> Option<u32> {
+    Some(match kind {
+        TokenKind::InfoSet | TokenKind::InfoClear => 0,
+        TokenKind::Call | TokenKind::NegatedCall => 1,
+        TokenKind::Argument => 2,
+        TokenKind::Chance => 3,
+        TokenKind::Output => 4,
+        TokenKind::Separator
+        | TokenKind::Punctuation
+        | TokenKind::ConditionDelimiter
+        | TokenKind::EffectDelimiter => 5,
+        TokenKind::Whitespace => return None,
+    })
+}
+
+fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let (connection, threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::INCREMENTAL),
+                save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                ..Default::default()
+            },
+        )),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(
+                ["=", "!", "+", "-", "{", "%", " "]
+                    .map(str::to_owned)
+                    .to_vec(),
+            ),
+            ..Default::default()
+        }),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: TOKEN_TYPES.to_vec(),
+                    token_modifiers: Vec::new(),
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    };
+    let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+    let params: InitializeParams = serde_json::from_value(params)?;
+
+    #[allow(deprecated)]
+    let root = params.root_uri.and_then(|x| x.to_file_path().ok());
+    let (tree, errors) = match root {
+        // Files that cannot be read are left out of the index.
+        Some(dir) => Tree::load_partial(&dir),
+        None => (Tree::default(), Vec::new()),
+    };
+    for message in errors {
+        let params = LogMessageParams {
+            typ: MessageType::WARNING,
+            message,
+        };
+        let notification = Notification::new(LogMessage::METHOD.to_owned(), params);
+        connection.sender.send(notification.into())?;
+    }
+    let mut server = Server {
+        index: InfoIndex::from_tree(&tree),
+        tree,
+        documents: BTreeMap::new(),
+    };
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    break;
+                }
+                connection.sender.send(server.handle(req).into())?;
+            }
+            Message::Notification(x) => {
+                if let Some(notification) = server.notify(x) {
+                    connection.sender.send(notification.into())?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    // The writer thread ends once the sender is gone.
+    drop(connection);
+    threads.join()?;
+    Ok(())
+}
+
+struct Server {
+    /// Workspace files as saved.
+    tree: Tree,
+    /// Open files, with their unsaved text.
+    documents: BTreeMap<PathBuf, Document>,
+    /// Info portions of the workspace, open files as they are edited.
+    index: InfoIndex,
+}
+
+impl Server {
+    fn handle(&mut self, req: Request) -> Response {
+        let id = req.id.clone();
+        let result = match req.method.as_str() {
+            HoverRequest::METHOD => params(req).and_then(|x| json(self.hover(x))),
+            GotoDefinition::METHOD => params(req).and_then(|x| json(self.definition(x))),
+            Completion::METHOD => params(req).and_then(|x| json(self.completion(x))),
+            Formatting::METHOD => params(req).and_then(|x| json(self.formatting(x))),
+            SemanticTokensFullRequest::METHOD => {
+                params(req).and_then(|x| json(self.semantic_tokens(x)))
+            }
+            x => {
+                let message = format!("unsupported request {}", x);
+                return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+            }
+        };
+        match result {
+            Ok(x) => Response::new_ok(id, x),
+            Err(e) => Response::new_err(id, ErrorCode::InvalidParams as i32, e),
+        }
+    }
+
+    /// Updates documents, answering with their diagnostics.
+    fn notify(&mut self, notification: Notification) -> Option<Notification> {
+        let uri = match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let x: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                // Dialog XML and scripts are for other servers.
+                let path = path(&x.text_document.uri);
+                if !is_ltx(&path) {
+                    return None;
+                }
+                let document = Document::new(x.text_document.text);
+                document.index(&path, &mut self.index);
+                self.documents.insert(path, document);
+                x.text_document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let x: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                let path = path(&x.text_document.uri);
+                let document = self.documents.get_mut(&path)?;
+                for change in x.content_changes {
+                    let Some(range) = change.range else {
+                        *document = Document::new(change.text);
+                        document.index(&path, &mut self.index);
+                        continue;
+                    };
+                    let text = document.text();
+                    let lines = LineIndex::new(text);
+                    let start = lines.offset(text, range.start.line, range.start.character);
+                    let end = lines.offset(text, range.end.line, range.end.character);
+                    // Reversed ranges are taken as they would be selected.
+                    let (start, end) = (start.min(end), start.max(end).min(text.len()));
+                    let span = Slice::new(start, end - start);
+                    document.edit_indexed(&path, &mut self.index, span, &change.text);
+                }
+                x.text_document.uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let x: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                let path = path(&x.text_document.uri);
+                self.documents.remove(&path);
+                // Back to what is saved, the edits may have been dropped.
+                self.reload(&path);
+                let params = PublishDiagnosticsParams {
+                    uri: x.text_document.uri,
+                    diagnostics: Vec::new(),
+                    version: None,
+                };
+                return Some(Notification::new(
+                    PublishDiagnostics::METHOD.to_owned(),
+                    params,
+                ));
+            }
+            DidSaveTextDocument::METHOD => {
+                let x: lsp_types::DidSaveTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                self.reload(&path(&x.text_document.uri));
+                return None;
+            }
+            _ => return None,
+        };
+
+        let path = path(&uri);
+        let document = self.documents.get(&path)?;
+
+        let text = document.text();
+        let lines = LineIndex::new(text);
+        let diagnostics = document
+            .lint()
+            .into_iter()
+            .map(|x| Diagnostic {
+                range: range(&lines, text, x.span),
+                severity: Some(match x.severity {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warning => DiagnosticSeverity::WARNING,
+                }),
+                code: Some(NumberOrString::String(x.code.to_owned())),
+                source: Some("condlist".to_owned()),
+                message: x.message,
+                ..Default::default()
+            })
+            .collect();
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        Some(Notification::new(
+            PublishDiagnostics::METHOD.to_owned(),
+            params,
+        ))
+    }
+
+    /// Reads a file from disk again, into the workspace and the index. Open
+    /// documents stay indexed as edited.
+    fn reload(&mut self, path: &Path) {
+        // A deleted file leaves nothing to index.
+        let file = SourceFile::read(path).ok();
+        if !self.documents.contains_key(path) {
+            self.index.remove_file(path);
+            if let Some(x) = &file {
+                self.index.add_file(x);
+            }
+        }
+        let Some(file) = file else {
+            return;
+        };
+        match self.tree.file_mut(path) {
+            Some(x) => *x = file,
+            None => self.tree.push(file),
+        }
+    }
+
+    /// The text of a file, unsaved if it is open.
+    fn text(&self, path: &Path) -> Option<&str> {
+        match self.documents.get(path) {
+            Some(x) => Some(x.text()),
+            None => self.tree.file(path).map(|x| x.text.as_str()),
+        }
+    }
+
+    /// An open document and the byte offset of a position in it.
+    fn document(&self, uri: &Url, position: Position) -> Result<(PathBuf, &str, usize), String> {
+        let path = path(uri);
+        let text = self
+            .documents
+            .get(&path)
+            .ok_or_else(|| format!("{} is not open", uri))?
+            .text();
+        let offset = LineIndex::new(text).offset(text, position.line, position.character);
+        Ok((path, text, offset))
+    }
+
+    fn hover(&self, params: lsp_types::HoverParams) -> Result<Option<Hover>, String> {
+        let x = params.text_document_position_params;
+        let (_, text, offset) = self.document(&x.text_document.uri, x.position)?;
+        let ltx = Ltx::from(text)?;
+        Ok(
+            ide::hover(&ltx, &self.index, offset).map(|(span, value)| Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+                range: Some(range(&LineIndex::new(text), text, span)),
+            }),
+        )
+    }
+
+    fn definition(
+        &self,
+        params: lsp_types::GotoDefinitionParams,
+    ) -> Result<GotoDefinitionResponse, String> {
+        let x = params.text_document_position_params;
+        let (path, text, offset) = self.document(&x.text_document.uri, x.position)?;
+        let ltx = Ltx::from(text)?;
+        let locations = ide::definitions(&ltx, &path, &self.index, offset)
+            .into_iter()
+            .filter_map(|(path, span)| {
+                let text = self.text(&path)?;
+                Some(Location {
+                    uri: Url::from_file_path(&path).ok()?,
+                    range: range(&LineIndex::new(text), text, span),
+                })
+            })
+            .collect();
+        Ok(GotoDefinitionResponse::Array(locations))
+    }
+
+    fn completion(
+        &self,
+        params: lsp_types::CompletionParams,
+    ) -> Result<CompletionResponse, String> {
+        let x = params.text_document_position;
+        let (_, text, offset) = self.document(&x.text_document.uri, x.position)?;
+        // Sections are only offered when the file is well-formed.
+        let ltx = Ltx::from(text).or_else(|_| Ltx::from(""))?;
+        let (span, completions) = ide::completions(&ltx, text, &self.index, offset);
+        let range = range(&LineIndex::new(text), text, span);
+        let items = completions
+            .into_iter()
+            .map(|x| CompletionItem {
+                kind: Some(match x.kind {
+                    SymbolKind::Info => CompletionItemKind::CONSTANT,
+                    SymbolKind::Function(_) => CompletionItemKind::FUNCTION,
+                    SymbolKind::Section => CompletionItemKind::MODULE,
+                }),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: x.label.clone(),
+                })),
+                label: x.label,
+                detail: x.detail,
+                ..Default::default()
+            })
+            .collect();
+        Ok(CompletionResponse::Array(items))
+    }
+
+    fn formatting(
+        &self,
+        params: lsp_types::DocumentFormattingParams,
+    ) -> Result<Vec<TextEdit>, String> {
+        let (_, text, _) = self.document(&params.text_document.uri, Position::default())?;
+        let ltx = Ltx::from(text)?;
+        let lines = LineIndex::new(text);
+        Ok(ide::format_edits(&ltx)
+            .into_iter()
+            .map(|x| TextEdit {
+                range: range(&lines, text, x.span),
+                new_text: x.text,
+            })
+            .collect())
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Result<SemanticTokens, String> {
+        let (_, text, _) = self.document(&params.text_document.uri, Position::default())?;
+        let ltx = Ltx::from(text)?;
+        let lines = LineIndex::new(text);
+        let mut data = Vec::new();
+        let mut previous = (0, 0);
+        for token in ide::semantic_tokens(&ltx) {
+            let Some(token_type) = token_type(token.kind) else {
+                continue;
+            };
+            let (line, start) = lines.position(text, token.span.index());
+            let delta_start = if line == previous.0 {
+                start - previous.1
+            } else {
+                start
+            };
+            data.push(SemanticToken {
+                delta_line: line - previous.0,
+                delta_start,
+                length: token.span.as_str(text).encode_utf16().count() as u32,
+                token_type,
+                token_modifiers_bitset: 0,
+            });
+            previous = (line, start);
+        }
+        Ok(SemanticTokens {
+            result_id: None,
+            data,
+        })
+    }
+}
+
+fn params<P: serde::de::DeserializeOwned>(req: Request) -> Result<P, String> {
+    serde_json::from_value(req.params).map_err(|e| e.to_string())
+}
+
+fn json(result: Result<impl serde::Serialize, String>) -> Result<serde_json::Value, String> {
+    serde_json::to_value(result?).map_err(|e| e.to_string())
+}
+
+fn is_ltx(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|x| x.eq_ignore_ascii_case("ltx"))
+}
+
+fn path(uri: &Url) -> PathBuf {
+    uri.to_file_path()
+        .unwrap_or_else(|_| Path::new(uri.path()).to_owned())
+}
+
+fn range(lines: &LineIndex, text: &str, span: Slice) -> Range {
+    let position = |offset| {
+        let (line, character) = lines.position(text, offset);
+        Position { line, character }
+    };
+    Range {
+        start: position(span.index()),
+        end: position(span.end()),
+    }
+}
@@ -0,0 +1,551 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use condlists_demystified::coverage::Coverage;
+use condlists_demystified::eval::State;
+use condlists_demystified::graph::Graph;
+use condlists_demystified::json;
+use condlists_demystified::lint::{Severity, lint_source};
+use condlists_demystified::ltx::Ltx;
+use condlists_demystified::parser::Ast;
+use condlists_demystified::rebuild::to_lua;
+use condlists_demystified::repl::Repl;
+use condlists_demystified::sim::{Event, Simulation, Timeline};
+use condlists_demystified::simplify::simplify;
+use condlists_demystified::table::DecisionTable;
+use condlists_demystified::trace::trace;
+use condlists_demystified::tree::Tree;
+use condlists_demystified::xref::InfoIndex;
+
+const USAGE: &str = "\
+usage: condlist <command> [options] [condlist...]
+
+Condlists are read from arguments, from files given with --file (one per line)
+or from stdin when neither is given.
+
+commands:
+  parse                 print the AST of every condlist as JSON
+  fmt                   print every condlist in canonical form
+  simplify              print every condlist simplified, with the same behaviour
+  compile --target lua  print the Lua code of every condlist
+  table                 print the decision table of every condlist
+      --format FORMAT       md (default), csv or html
+  eval                  evaluate every condlist against a simulated world
+      --give INFO           start with INFO given
+      --stub F[(A:B)]=BOOL  result of condition F, for any or the given arguments
+      --seed N              seed of the random generator used by ~N
+      --trace               also print every effect run and what it changed
+  repl                  evaluate condlists typed on stdin, see :help
+                        (takes the options of eval)
+  sim <file>            run the logic of an LTX file tick by tick
+      --ticks N             ticks after the start, 10 by default
+      --at TICK:EVENT       before TICK, +INFO, -INFO or F[(A:B)]=BOOL
+      --coverage PATH       write condlist coverage, HTML if PATH ends with
+                            .html, lcov otherwise
+                        (also takes the options of eval)
+  graph <file>          print the section transitions of an LTX file
+      --format FORMAT       dot (default) or mermaid
+  lint <dir>            check every LTX and XML file below <dir>
+  test <file...>        run the scenarios of TOML files, needs the scenario
+                        feature
+      --coverage PATH       as for sim
+
+options:
+  -f, --file PATH       read condlists from PATH
+      --json            machine-readable output
+
+exit codes: 0 success, 1 invalid input, lint errors or failed scenarios,
+2 bad usage";
+
+/// Command line failures, mapped to exit codes.
+enum Failure {
+    Input,
+    Usage(String),
+}
+
+#[derive(Default)]
+struct Args {
+    command: String,
+    positional: Vec<String>,
+    files: Vec<String>,
+    give: Vec<String>,
+    stubs: Vec<String>,
+    seed: Option<u64>,
+    ticks: Option<usize>,
+    at: Vec<String>,
+    coverage: Option<String>,
+    target: Option<String>,
+    format: Option<String>,
+    json: bool,
+    trace: bool,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut out = Self {
+            command: args.next().ok_or("missing command")?,
+            ..Default::default()
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "-f" | "--file" => out.files.push(value()?),
+                "--give" => out.give.push(value()?),
+                "--stub" => out.stubs.push(value()?),
+                "--seed" => {
+                    out.seed = Some(value()?.parse().map_err(|e| format!("--seed: {}", e))?)
+                }
+                "--ticks" => {
+                    out.ticks = Some(value()?.parse().map_err(|e| format!("--ticks: {}", e))?)
+                }
+                "--at" => out.at.push(value()?),
+                "--coverage" => out.coverage = Some(value()?),
+                "--target" => out.target = Some(value()?),
+                "--format" => out.format = Some(value()?),
+                "--json" => out.json = true,
+                "--trace" => out.trace = true,
+                x if x.starts_with("--") => return Err(format!("unknown option {}", x)),
+                _ => out.positional.push(arg),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Condlists from arguments, files or stdin.
+    fn inputs(&self) -> Result<Vec<String>, String> {
+        let mut out = self.positional.clone();
+        for file in &self.files {
+            let text = std::fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))?;
+            out.extend(text.lines().map(|x| x.to_owned()));
+        }
+        if self.positional.is_empty() && self.files.is_empty() {
+            for line in io::stdin().lock().lines() {
+                out.push(line.map_err(|e| e.to_string())?);
+            }
+        }
+        out.retain(|x| !x.trim().is_empty());
+        Ok(out)
+    }
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = match args.command.as_str() {
+        "parse" => each_condlist(&args, |ast| Ok(json::ast(ast))),
+        "fmt" => each_condlist(&args, |ast| Ok(ast.to_string())),
+        "simplify" => each_condlist(&args, |ast| simplify(ast).map(|x| x.to_string())),
+        "compile" => match args.target.as_deref() {
+            Some("lua") => each_condlist(&args, |ast| Ok(to_lua(ast).trim_end().to_owned())),
+            Some(x) => Err(Failure::Usage(format!("unknown target {}", x))),
+            None => Err(Failure::Usage("compile needs --target".to_owned())),
+        },
+        "table" => table(&args),
+        "eval" => eval(&args),
+        "repl" => repl(&args),
+        "sim" => sim(&args),
+        "graph" => graph(&args),
+        "lint" => lint(&args),
+        "test" => test(&args),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        x => Err(Failure::Usage(format!("unknown command {}", x))),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Input) => ExitCode::from(1),
+        Err(Failure::Usage(e)) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Parses every input and prints what `f` makes of it. Failing inputs are
+/// reported and skipped.
+fn each_condlist(
+    args: &Args,
+    mut f: impl FnMut(&Ast) -> Result<String, String>,
+) -> Result<(), Failure> {
+    let mut failed = false;
+    let inputs = args.inputs().map_err(|e| {
+        eprintln!("error: {}", e);
+        Failure::Input
+    })?;
+    for src in inputs {
+        match Ast::from(&src).and_then(|ast| f(&ast)) {
+            Ok(x) => println!("{}", x),
+            Err(e) => {
+                failed = true;
+                if args.json {
+                    println!("{{\"error\":{}}}", json::string(&e));
+                } else {
+                    eprintln!("error: {}: {}", src, e);
+                }
+            }
+        }
+    }
+    if failed { Err(Failure::Input) } else { Ok(()) }
+}
+
+fn table(args: &Args) -> Result<(), Failure> {
+    let render = match args.format.as_deref().unwrap_or("md") {
+        "md" => DecisionTable::to_markdown,
+        "csv" => DecisionTable::to_csv,
+        "html" => DecisionTable::to_html,
+        x => return Err(Failure::Usage(format!("unknown format {}", x))),
+    };
+    each_condlist(args, |ast| {
+        DecisionTable::from_ast(ast).map(|x| render(&x).trim_end().to_owned())
+    })
+}
+
+/// The world of `eval` and `repl`, from `--give`, `--stub` and `--seed`.
+fn state(args: &Args) -> Result<State, Failure> {
+    let mut state = State::with_seed(args.seed.unwrap_or_default());
+    for info in &args.give {
+        state.infos.insert(info.clone());
+    }
+    for stub in &args.stubs {
+        state
+            .stub_str(stub)
+            .map_err(|e| Failure::Usage(format!("--stub {}", e)))?;
+    }
+    Ok(state)
+}
+
+fn eval(args: &Args) -> Result<(), Failure> {
+    let mut state = state(args)?;
+    each_condlist(args, |ast| {
+        let step = trace(ast, &mut state)?;
+        let output = step.outcome.output.unwrap_or_else(|| "nil".to_owned());
+        Ok(if args.json {
+            let mut out = format!(
+                "{{\"statement\":{},\"output\":{},\"infos\":[{}],\"calls\":[{}]",
+                step.outcome
+                    .statement
+                    .map(|x| x.to_string())
+                    .unwrap_or_else(|| "null".to_owned()),
+                json::string(&output),
+                strings(state.infos.iter().cloned()),
+                strings(
+                    state
+                        .calls
+                        .iter()
+                        .map(|(f, a)| format!("{}({})", f, a.join(":")))
+                ),
+            );
+            if args.trace {
+                out.push_str(&format!(
+                    ",\"actions\":[{}],\"diff\":{{\"given\":[{}],\"disabled\":[{}],\"calls\":[{}]}}",
+                    strings(step.actions.iter().map(|x| x.to_string())),
+                    strings(step.diff.given.iter().cloned()),
+                    strings(step.diff.disabled.iter().cloned()),
+                    strings(
+                        step
+                            .diff
+                            .calls
+                            .iter()
+                            .map(|(f, a)| format!("{}({})", f, a.join(":")))
+                    ),
+                ));
+            }
+            out.push('}');
+            out
+        } else if args.trace {
+            let mut out = output;
+            for action in &step.actions {
+                out.push_str(&format!("\n  {}", action));
+            }
+            if !step.diff.is_empty() {
+                out.push_str(&format!("\n  changed: {}", step.diff));
+            }
+            out
+        } else {
+            output
+        })
+    })
+}
+
+/// Elements of a JSON array of strings.
+fn strings(items: impl Iterator<Item = String>) -> String {
+    items
+        .map(|x| json::string(&x))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn repl(args: &Args) -> Result<(), Failure> {
+    let mut repl = Repl::new(state(args)?);
+    let prompt = io::stdin().is_terminal();
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        if prompt {
+            print!("> ");
+            io::stdout()
+                .flush()
+                .map_err(|e| Failure::Usage(e.to_string()))?;
+        }
+        let Some(line) = lines.next() else {
+            return Ok(());
+        };
+        match repl.line(&line.map_err(|e| Failure::Usage(e.to_string()))?) {
+            None => return Ok(()),
+            Some(Ok(x)) if x.is_empty() => {}
+            Some(Ok(x)) => println!("{}", x),
+            Some(Err(e)) => eprintln!("error: {}", e),
+        }
+    }
+}
+
+fn sim(args: &Args) -> Result<(), Failure> {
+    let [file] = args.positional.as_slice() else {
+        return Err(Failure::Usage("sim needs exactly one file".to_owned()));
+    };
+    let mut timeline = Timeline::default();
+    for at in &args.at {
+        let (tick, event) = at
+            .split_once(':')
+            .and_then(|(tick, event)| Some((tick.parse().ok()?, event)))
+            .ok_or_else(|| Failure::Usage(format!("--at {}: expected TICK:EVENT", at)))?;
+        let event = Event::parse(event).map_err(|e| Failure::Usage(format!("--at {}", e)))?;
+        timeline.at(tick, event);
+    }
+    let state = state(args)?;
+    let text = std::fs::read_to_string(file).map_err(|e| {
+        eprintln!("error: {}: {}", file, e);
+        Failure::Input
+    })?;
+    let ltx = match Ltx::from(&text) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("error: {}: {}", file, e);
+            return Err(Failure::Input);
+        }
+    };
+
+    let mut sim = Simulation::new(&ltx, state);
+    let result = sim.run(&timeline, args.ticks.unwrap_or(10));
+    let mut coverage = Coverage::default();
+    coverage.add_file(Path::new(file), &text);
+    for x in sim.log.iter().flat_map(|x| &x.evaluations) {
+        coverage.record(Path::new(file), &x.value, &x.step);
+    }
+    write_coverage(args, &coverage)?;
+    if args.json {
+        let name = |x: &Option<String>| x.as_deref().map(json::string).unwrap_or("null".to_owned());
+        let ticks = sim.log.iter().map(|tick| {
+            let evaluations = tick.evaluations.iter().map(|x| {
+                format!(
+                    "{{\"key\":{},\"output\":{},\"actions\":[{}]}}",
+                    json::string(&x.key),
+                    name(&x.step.outcome.output),
+                    strings(x.step.actions.iter().map(|x| x.to_string()))
+                )
+            });
+            format!(
+                "{{\"tick\":{},\"events\":[{}],\"from\":{},\"section\":{},\"evaluations\":[{}]}}",
+                tick.tick,
+                strings(tick.events.iter().map(|x| x.to_string())),
+                name(&tick.from),
+                name(&tick.section),
+                evaluations.collect::<Vec<_>>().join(",")
+            )
+        });
+        println!("[{}]", ticks.collect::<Vec<_>>().join(","));
+    } else {
+        sim.log.iter().for_each(|x| println!("{}", x));
+    }
+
+    result.map_err(|e| {
+        eprintln!("error: {}: {}", file, e);
+        Failure::Input
+    })
+}
+
+/// Writes `--coverage`, if given.
+fn write_coverage(args: &Args, coverage: &Coverage) -> Result<(), Failure> {
+    let Some(path) = &args.coverage else {
+        return Ok(());
+    };
+    let text = if path.ends_with(".html") {
+        coverage.to_html()
+    } else {
+        coverage.to_lcov()
+    };
+    std::fs::write(path, text).map_err(|e| {
+        eprintln!("error: {}: {}", path, e);
+        Failure::Input
+    })
+}
+
+fn graph(args: &Args) -> Result<(), Failure> {
+    let [file] = args.positional.as_slice() else {
+        return Err(Failure::Usage("graph needs exactly one file".to_owned()));
+    };
+    let render = match args.format.as_deref().unwrap_or("dot") {
+        "dot" => Graph::to_dot,
+        "mermaid" => Graph::to_mermaid,
+        x => return Err(Failure::Usage(format!("unknown format {}", x))),
+    };
+    let text = std::fs::read_to_string(file).map_err(|e| {
+        eprintln!("error: {}: {}", file, e);
+        Failure::Input
+    })?;
+    let graph = Ltx::from(&text).and_then(|ltx| Graph::from_ltx(&ltx));
+    match graph {
+        Ok(x) => {
+            print!("{}", render(&x));
+            Ok(())
+        }
+        Err(e) => {
+            eprintln!("error: {}: {}", file, e);
+            Err(Failure::Input)
+        }
+    }
+}
+
+fn lint(args: &Args) -> Result<(), Failure> {
+    let [dir] = args.positional.as_slice() else {
+        return Err(Failure::Usage(
+            "lint needs exactly one directory".to_owned(),
+        ));
+    };
+    let tree = Tree::load(Path::new(dir)).map_err(|e| {
+        eprintln!("error: {}", e);
+        Failure::Input
+    })?;
+
+    let mut diagnostics = Vec::new();
+    for file in tree.files().iter().filter(|x| x.is_ltx()) {
+        diagnostics.extend(lint_source(&file.text).into_iter().map(|x| (file, x)));
+    }
+    for (path, diagnostic) in InfoIndex::from_tree(&tree).check() {
+        if let Some(file) = tree.file(&path) {
+            diagnostics.push((file, diagnostic));
+        }
+    }
+
+    let mut failed = false;
+    let mut lines = Vec::new();
+    for (file, x) in &diagnostics {
+        failed |= x.severity == Severity::Error;
+        let (line, column) = file.line_col(x.span.index());
+        let severity = match x.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        lines.push(if args.json {
+            format!(
+                "{{\"file\":{},\"line\":{},\"column\":{},\"severity\":\"{}\",\"code\":\"{}\",\"message\":{}}}",
+                json::string(&file.path.display().to_string()),
+                line,
+                column,
+                severity,
+                x.code,
+                json::string(&x.message)
+            )
+        } else {
+            format!(
+                "{}:{}:{}: {}[{}]: {}",
+                file.path.display(),
+                line,
+                column,
+                severity,
+                x.code,
+                x.message
+            )
+        });
+    }
+    if args.json {
+        println!("[{}]", lines.join(","));
+    } else {
+        lines.iter().for_each(|x| println!("{}", x));
+    }
+
+    if failed { Err(Failure::Input) } else { Ok(()) }
+}
+
+#[cfg(feature = "scenario")]
+fn test(args: &Args) -> Result<(), Failure> {
+    use condlists_demystified::scenario::from_toml;
+
+    if args.positional.is_empty() {
+        return Err(Failure::Usage(
+            "test needs at least one scenario file".to_owned(),
+        ));
+    }
+    let (mut passed, mut failed) = (0, 0);
+    let mut lines = Vec::new();
+    let mut coverage = Coverage::default();
+    for file in &args.positional {
+        let text = std::fs::read_to_string(file).map_err(|e| {
+            eprintln!("error: {}: {}", file, e);
+            Failure::Input
+        })?;
+        let scenarios = match from_toml(&text) {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("error: {}: {}", file, e);
+                return Err(Failure::Input);
+            }
+        };
+        let dir = Path::new(file).parent().unwrap_or(Path::new("."));
+        for scenario in scenarios {
+            let messages = scenario.run_covered(dir, &mut coverage);
+            if messages.is_empty() {
+                passed += 1;
+            } else {
+                failed += 1;
+            }
+            lines.push(if args.json {
+                format!(
+                    "{{\"file\":{},\"name\":{},\"passed\":{},\"messages\":[{}]}}",
+                    json::string(file),
+                    json::string(&scenario.name),
+                    messages.is_empty(),
+                    messages
+                        .iter()
+                        .map(|x| json::string(x))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                )
+            } else {
+                let status = if messages.is_empty() { "ok" } else { "FAIL" };
+                let mut line = format!("{}: {}: {}", status, file, scenario.name);
+                for message in &messages {
+                    line.push_str(&format!("\n  {}", message));
+                }
+                line
+            });
+        }
+    }
+    if args.json {
+        println!("[{}]", lines.join(","));
+    } else {
+        lines.iter().for_each(|x| println!("{}", x));
+        println!("{} passed, {} failed", passed, failed);
+    }
+    write_coverage(args, &coverage)?;
+
+    if failed > 0 {
+        Err(Failure::Input)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "scenario"))]
+fn test(_: &Args) -> Result<(), Failure> {
+    Err(Failure::Usage("test needs the scenario feature".to_owned()))
+}
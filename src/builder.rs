@@ -0,0 +1,328 @@
+use crate::parser::{Ast, Block, OwnedAst, Statement, is_valid_name};
+
+/// Builds a statement from code, e.g.
+/// `Statement::builder().when_info("x").when_call("is_alive", ["wolf"]).give("y").then("walker@2")`.
+///
+/// Blocks are kept as text and checked when the statement is built, since a
+/// `Block` only makes sense together with the source it points into.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatementBuilder {
+    conditions: Option<Vec<String>>,
+    effects: Option<Vec<String>>,
+    out: Option<String>,
+    names: Vec<String>,
+}
+
+impl Statement {
+    pub fn builder() -> StatementBuilder {
+        StatementBuilder::default()
+    }
+}
+
+impl StatementBuilder {
+    fn condition(mut self, text: String) -> Self {
+        self.conditions.get_or_insert_with(Vec::new).push(text);
+        self
+    }
+
+    fn effect(mut self, text: String) -> Self {
+        self.effects.get_or_insert_with(Vec::new).push(text);
+        self
+    }
+
+    fn name<'s>(&mut self, name: &'s str) -> &'s str {
+        self.names.push(name.to_owned());
+        name
+    }
+
+    fn call<'s>(
+        &mut self,
+        prefix: char,
+        function: &str,
+        args: impl IntoIterator<Item = &'s str>,
+    ) -> String {
+        let mut out = format!("{}{}", prefix, self.name(function));
+        let args = args.into_iter().map(|x| self.name(x)).collect::<Vec<_>>();
+        if !args.is_empty() {
+            out.push_str(&format!("({})", args.join(":")));
+        }
+        out
+    }
+
+    /// `{+key}`
+    pub fn when_info(mut self, key: &str) -> Self {
+        let text = format!("+{}", self.name(key));
+        self.condition(text)
+    }
+
+    /// `{-key}`
+    pub fn when_no_info(mut self, key: &str) -> Self {
+        let text = format!("-{}", self.name(key));
+        self.condition(text)
+    }
+
+    /// `{=function(args)}`
+    pub fn when_call<'s>(
+        mut self,
+        function: &str,
+        args: impl IntoIterator<Item = &'s str>,
+    ) -> Self {
+        let text = self.call('=', function, args);
+        self.condition(text)
+    }
+
+    /// `{!function(args)}`
+    pub fn when_not_call<'s>(
+        mut self,
+        function: &str,
+        args: impl IntoIterator<Item = &'s str>,
+    ) -> Self {
+        let text = self.call('!', function, args);
+        self.condition(text)
+    }
+
+    /// `{~percent}`
+    pub fn when_chance(self, percent: u32) -> Self {
+        self.condition(format!("~{}", percent))
+    }
+
+    /// An empty condition, `{}`.
+    pub fn always(mut self) -> Self {
+        self.conditions.get_or_insert_with(Vec::new);
+        self
+    }
+
+    /// `%+key%`
+    pub fn give(mut self, key: &str) -> Self {
+        let text = format!("+{}", self.name(key));
+        self.effect(text)
+    }
+
+    /// `%-key%`
+    pub fn disable(mut self, key: &str) -> Self {
+        let text = format!("-{}", self.name(key));
+        self.effect(text)
+    }
+
+    /// `%=function(args)%`
+    pub fn run<'s>(mut self, function: &str, args: impl IntoIterator<Item = &'s str>) -> Self {
+        let text = self.call('=', function, args);
+        self.effect(text)
+    }
+
+    /// Sets the output section.
+    pub fn then(mut self, out: &str) -> Self {
+        self.name(out);
+        self.out = Some(out.to_owned());
+        self
+    }
+
+    fn text(&self) -> Result<String, String> {
+        if let Some(x) = self.names.iter().find(|x| !is_valid_name(x)) {
+            return Err(format!("`{}` is not a valid name", x));
+        }
+        let mut parts = Vec::new();
+        if let Some(x) = &self.conditions {
+            parts.push(format!("{{{}}}", x.join(" ")));
+        }
+        if let Some(x) = &self.out {
+            parts.push(x.clone());
+        }
+        if let Some(x) = &self.effects {
+            parts.push(format!("%{}%", x.join(" ")));
+        }
+        Ok(parts.join(" "))
+    }
+
+    /// Builds a condlist made of this single statement.
+    pub fn build(self) -> Result<OwnedAst, String> {
+        Ast::from_string(self.text()?)
+    }
+}
+
+/// Builds a condlist statement by statement.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AstBuilder {
+    statements: Vec<StatementBuilder>,
+}
+
+impl AstBuilder {
+    pub fn statement(mut self, statement: StatementBuilder) -> Self {
+        self.statements.push(statement);
+        self
+    }
+
+    pub fn build(self) -> Result<OwnedAst, String> {
+        let texts = self
+            .statements
+            .iter()
+            .map(|x| x.text())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ast::from_string(texts.join(", "))
+    }
+}
+
+/// In-place mutation. Every change rewrites the source in canonical form, see
+/// [`Ast::format_statement`].
+impl Ast<'_> {
+    pub fn builder() -> AstBuilder {
+        AstBuilder::default()
+    }
+
+    fn rewrite(
+        &mut self,
+        f: impl FnOnce(&mut Vec<String>) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut texts = self
+            .statements()
+            .iter()
+            .map(|x| self.format_statement(x))
+            .collect::<Vec<_>>();
+        f(&mut texts)?;
+        *self = Ast::from_string(texts.join(", "))?;
+        Ok(())
+    }
+
+    fn check_index(&self, ix: usize) -> Result<(), String> {
+        if ix >= self.statements().len() {
+            return Err(format!("No statement {}", ix));
+        }
+        Ok(())
+    }
+
+    pub fn push_statement(&mut self, statement: StatementBuilder) -> Result<(), String> {
+        let text = statement.text()?;
+        self.rewrite(|x| {
+            x.push(text);
+            Ok(())
+        })
+    }
+
+    pub fn insert_statement(
+        &mut self,
+        ix: usize,
+        statement: StatementBuilder,
+    ) -> Result<(), String> {
+        if ix > self.statements().len() {
+            return Err(format!("No statement {}", ix));
+        }
+        let text = statement.text()?;
+        self.rewrite(|x| {
+            x.insert(ix, text);
+            Ok(())
+        })
+    }
+
+    pub fn replace_statement(
+        &mut self,
+        ix: usize,
+        statement: StatementBuilder,
+    ) -> Result<(), String> {
+        self.check_index(ix)?;
+        let text = statement.text()?;
+        self.rewrite(|x| {
+            x[ix] = text;
+            Ok(())
+        })
+    }
+
+    pub fn remove_statement(&mut self, ix: usize) -> Result<(), String> {
+        self.check_index(ix)?;
+        self.rewrite(|x| {
+            x.remove(ix);
+            Ok(())
+        })
+    }
+
+    /// Adds blocks to statement `ix` by passing it through `f` as a builder.
+    pub fn extend_statement(
+        &mut self,
+        ix: usize,
+        f: impl FnOnce(StatementBuilder) -> StatementBuilder,
+    ) -> Result<(), String> {
+        self.check_index(ix)?;
+        let statement = &self.statements()[ix];
+        let blocks = |x: &[Block]| x.iter().map(|b| self.format_block(b)).collect::<Vec<_>>();
+        let builder = StatementBuilder {
+            conditions: statement.conditions().map(|x| blocks(x.blocks())),
+            effects: statement.effects().map(|x| blocks(x.blocks())),
+            out: statement.val().map(|x| self.slice_as_str(x).to_owned()),
+            names: Vec::new(),
+        };
+        self.replace_statement(ix, f(builder))
+    }
+
+    /// Sets or removes the output of statement `ix`.
+    pub fn set_output(&mut self, ix: usize, out: Option<&str>) -> Result<(), String> {
+        self.check_index(ix)?;
+        if let Some(x) = out.filter(|x| !is_valid_name(x)) {
+            return Err(format!("`{}` is not a valid name", x));
+        }
+        self.extend_statement(ix, |mut x| {
+            x.out = out.map(|o| o.to_owned());
+            x
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statement() {
+        let ast = Statement::builder()
+            .when_info("x")
+            .when_call("is_alive", ["wolf"])
+            .give("y")
+            .then("walker@2")
+            .build()
+            .unwrap();
+        assert_eq!(ast.to_string(), "{+x =is_alive(wolf)} walker@2 %+y%");
+    }
+
+    #[test]
+    fn ast() {
+        let ast = Ast::builder()
+            .statement(
+                Statement::builder()
+                    .when_no_info("a")
+                    .when_not_call("f", [])
+                    .when_chance(30)
+                    .run("hit", ["npc", "10"])
+                    .disable("b")
+                    .then("X"),
+            )
+            .statement(Statement::builder().always().then("Y"))
+            .statement(Statement::builder().then("Z"))
+            .build()
+            .unwrap();
+        assert_eq!(ast.to_string(), "{-a !f ~30} X %=hit(npc:10) -b%, {} Y, Z");
+    }
+
+    #[test]
+    fn invalid_name() {
+        assert!(Statement::builder().when_info("a b").build().is_err());
+        assert!(Statement::builder().then("x,y").build().is_err());
+        assert!(
+            Statement::builder()
+                .when_call("f", ["a:b"])
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn mutation() {
+        let mut ast = Ast::from("{+a}  X, Y").unwrap();
+        ast.push_statement(Statement::builder().then("Z")).unwrap();
+        ast.insert_statement(0, Statement::builder().when_info("b").then("W"))
+            .unwrap();
+        ast.extend_statement(1, |x| x.when_info("c").give("d"))
+            .unwrap();
+        ast.set_output(2, Some("V")).unwrap();
+        ast.remove_statement(3).unwrap();
+        assert_eq!(ast.to_string(), "{+b} W, {+a +c} X %+d%, V");
+        assert!(ast.remove_statement(3).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Known functions of `xr_conditions.script` and `xr_effects.script`, for
+//! hover and completion. Mods add their own, so a missing function is not an
+//! error.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `=f` and `!f` in conditions.
+    Condition,
+    /// `=f` in effects.
+    Effect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Function {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// Parameter names, joined with `:` in the condlist.
+    pub params: &'static [&'static str],
+    pub doc: &'static str,
+    /// Running the function changes the game beyond its result, so it
+    /// matters whether it runs, see [`crate::order`]. Always true for
+    /// effects.
+    pub side_effects: bool,
+}
+
+impl Function {
+    /// `=name(a:b)` as it would be written in a condlist.
+    pub fn signature(&self) -> String {
+        if self.params.is_empty() {
+            format!("={}", self.name)
+        } else {
+            format!("={}({})", self.name, self.params.join(":"))
+        }
+    }
+}
+
+const fn condition(
+    name: &'static str,
+    params: &'static [&'static str],
+    doc: &'static str,
+) -> Function {
+    Function {
+        name,
+        kind: Kind::Condition,
+        params,
+        doc,
+        side_effects: false,
+    }
+}
+
+/// A condition with side effects.
+const fn impure_condition(
+    name: &'static str,
+    params: &'static [&'static str],
+    doc: &'static str,
+) -> Function {
+    Function {
+        side_effects: true,
+        ..condition(name, params, doc)
+    }
+}
+
+const fn effect(
+    name: &'static str,
+    params: &'static [&'static str],
+    doc: &'static str,
+) -> Function {
+    Function {
+        name,
+        kind: Kind::Effect,
+        params,
+        doc,
+        side_effects: true,
+    }
+}
+
+const FUNCTIONS: &[Function] = &[
+    condition(
+        "is_alive",
+        &["story_id"],
+        "The object with this story id exists and is alive.",
+    ),
+    condition(
+        "is_dead",
+        &["story_id"],
+        "The object with this story id is dead.",
+    ),
+    condition(
+        "actor_in_zone",
+        &["zone"],
+        "The actor is inside the space restrictor `zone`.",
+    ),
+    condition(
+        "actor_out_zone",
+        &["zone"],
+        "The actor is outside the space restrictor `zone`.",
+    ),
+    condition(
+        "npc_in_zone",
+        &["story_id", "zone"],
+        "The object is inside the space restrictor `zone`.",
+    ),
+    condition(
+        "dist_to_actor_le",
+        &["distance"],
+        "The NPC is at most `distance` meters away from the actor.",
+    ),
+    condition(
+        "dist_to_actor_ge",
+        &["distance"],
+        "The NPC is at least `distance` meters away from the actor.",
+    ),
+    condition(
+        "actor_has_item",
+        &["section"],
+        "The actor carries an item of this section.",
+    ),
+    condition("see_actor", &[], "The NPC sees the actor."),
+    condition("actor_enemy", &[], "The NPC is an enemy of the actor."),
+    condition("actor_friend", &[], "The NPC is a friend of the actor."),
+    condition("hit_by_actor", &[], "The NPC was last hit by the actor."),
+    condition("killed_by_actor", &[], "The NPC was killed by the actor."),
+    condition("is_wounded", &[], "The NPC is wounded."),
+    condition(
+        "signal",
+        &["name"],
+        "The active scheme raised `signal`, e.g. `path_end`.",
+    ),
+    condition("is_day", &[], "It is daytime in the game."),
+    condition(
+        "squad_exist",
+        &["story_id"],
+        "A squad with this story id exists.",
+    ),
+    condition(
+        "counter_greater",
+        &["name", "value"],
+        "The counter `name` is greater than `value`.",
+    ),
+    impure_condition(
+        "random_choice",
+        &["percent"],
+        "Holds with `percent` chance, drawing from the same generator as `~N`.",
+    ),
+    effect(
+        "give_task",
+        &["task_id"],
+        "Gives the task `task_id` to the actor.",
+    ),
+    effect(
+        "kill_npc",
+        &["story_id"],
+        "Kills the object with this story id.",
+    ),
+    effect(
+        "spawn_object",
+        &["section", "path"],
+        "Spawns an object of `section` at the first point of `path`.",
+    ),
+    effect(
+        "teleport_actor",
+        &["path", "look"],
+        "Moves the actor to the first point of `path`, looking towards `look`.",
+    ),
+    effect(
+        "play_sound",
+        &["theme"],
+        "The NPC plays a sound of this theme.",
+    ),
+    effect("disable_ui", &[], "Hides the interface and blocks input."),
+    effect("enable_ui", &[], "Shows the interface and restores input."),
+    effect(
+        "inc_counter",
+        &["name", "value"],
+        "Adds `value`, 1 by default, to the counter `name`.",
+    ),
+    effect(
+        "set_counter",
+        &["name", "value"],
+        "Sets the counter `name` to `value`.",
+    ),
+    effect(
+        "remove_item",
+        &["section"],
+        "Takes an item of `section` from the actor.",
+    ),
+    effect(
+        "create_squad",
+        &["squad", "smart"],
+        "Spawns the squad `squad` on the smart terrain `smart`.",
+    ),
+    effect(
+        "remove_squad",
+        &["story_id"],
+        "Removes the squad with this story id.",
+    ),
+];
+
+pub fn functions() -> &'static [Function] {
+    FUNCTIONS
+}
+
+pub fn lookup(kind: Kind, name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|x| x.kind == kind && x.name == name)
+}
@@ -0,0 +1,468 @@
+//! Which parts of the condlists of LTX files evaluations went through: how
+//! often each statement was chosen and each condition block came out true
+//! and false. Every transition of a file counts, so logic no test reaches
+//! shows up as uncovered.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::ltx::Ltx;
+use crate::parser::{Ast, Block, Slice};
+use crate::trace::Step;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Hits {
+    evaluations: usize,
+    /// Times each statement was chosen.
+    statements: BTreeMap<usize, usize>,
+    /// Times each condition block was true and false.
+    blocks: BTreeMap<(usize, usize), [usize; 2]>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct File {
+    text: String,
+    /// Condlists by value position.
+    condlists: BTreeMap<usize, Hits>,
+}
+
+/// Covered and total counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub statements: (usize, usize),
+    /// Each condition block has two branches, true and false.
+    pub branches: (usize, usize),
+}
+
+impl Totals {
+    fn add(&mut self, other: Totals) {
+        self.statements.0 += other.statements.0;
+        self.statements.1 += other.statements.1;
+        self.branches.0 += other.branches.0;
+        self.branches.1 += other.branches.1;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileTotals {
+    pub path: PathBuf,
+    pub total: Totals,
+    /// In file order.
+    pub sections: Vec<(String, Totals)>,
+}
+
+/// A condlist of a file, with its hits.
+struct Entry<'a> {
+    section: &'a str,
+    key: &'a str,
+    line: usize,
+    ast: Ast<'a>,
+    hits: &'a Hits,
+}
+
+impl Entry<'_> {
+    fn conditions(&self, statement: usize) -> &[Block] {
+        let statement = &self.ast.statements()[statement];
+        statement.conditions().map(|x| x.blocks()).unwrap_or(&[])
+    }
+
+    fn chosen(&self, statement: usize) -> usize {
+        self.hits.statements.get(&statement).copied().unwrap_or(0)
+    }
+
+    /// `None` if the block was never checked.
+    fn branches(&self, statement: usize, block: usize) -> Option<[usize; 2]> {
+        self.hits.blocks.get(&(statement, block)).copied()
+    }
+
+    fn totals(&self) -> Totals {
+        let mut out = Totals::default();
+        for i in 0..self.ast.statements().len() {
+            out.statements.1 += 1;
+            out.statements.0 += (self.chosen(i) > 0) as usize;
+            for j in 0..self.conditions(i).len() {
+                let [yes, no] = self.branches(i, j).unwrap_or_default();
+                out.branches.1 += 2;
+                out.branches.0 += (yes > 0) as usize + (no > 0) as usize;
+            }
+        }
+        out
+    }
+
+    /// `[section] key #n`, naming a statement.
+    fn name(&self, statement: usize) -> String {
+        format!("[{}] {} #{}", self.section, self.key, statement + 1)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    files: BTreeMap<PathBuf, File>,
+}
+
+impl Coverage {
+    /// Makes every transition of an LTX file part of the report. Adding a
+    /// file again keeps its hits.
+    pub fn add_file(&mut self, path: &Path, text: &str) {
+        let file = self.files.entry(path.to_owned()).or_default();
+        if file.text != text {
+            *file = File {
+                text: text.to_owned(),
+                condlists: BTreeMap::new(),
+            };
+        }
+        let Ok(ltx) = Ltx::from(text) else {
+            return;
+        };
+        for x in ltx.transitions() {
+            file.condlists.entry(x.value.index()).or_default();
+        }
+    }
+
+    /// Counts an evaluation of the condlist at `value` of a file added with
+    /// [`Coverage::add_file`].
+    pub fn record(&mut self, path: &Path, value: &Slice, step: &Step) {
+        let Some(file) = self.files.get_mut(path) else {
+            return;
+        };
+        let hits = file.condlists.entry(value.index()).or_default();
+        hits.evaluations += 1;
+        if let Some(i) = step.outcome.statement {
+            *hits.statements.entry(i).or_default() += 1;
+        }
+        for (i, j, value) in &step.checked {
+            hits.blocks.entry((*i, *j)).or_default()[!*value as usize] += 1;
+        }
+    }
+
+    /// Every condlist of `file` that parses, in file order.
+    fn entries<'a>(ltx: &'a Ltx<'a>, file: &'a File) -> Vec<Entry<'a>> {
+        let mut out = Vec::new();
+        for x in ltx.condlists() {
+            let Some(hits) = file.condlists.get(&x.value.index()) else {
+                continue;
+            };
+            let Ok(ast) = ltx.parse(&x) else {
+                continue;
+            };
+            out.push(Entry {
+                section: ltx.slice_as_str(x.section.name()),
+                key: ltx.slice_as_str(x.entry.key()),
+                line: file.text[..x.value.index()].matches('\n').count() + 1,
+                ast,
+                hits,
+            });
+        }
+        out
+    }
+
+    /// Totals by file, and by section within each file.
+    pub fn totals(&self) -> Vec<FileTotals> {
+        let mut out = Vec::new();
+        for (path, file) in &self.files {
+            let Ok(ltx) = Ltx::from(&file.text) else {
+                continue;
+            };
+            let mut total = Totals::default();
+            let mut sections: Vec<(String, Totals)> = Vec::new();
+            for entry in Self::entries(&ltx, file) {
+                let totals = entry.totals();
+                total.add(totals);
+                match sections.last_mut() {
+                    Some((name, x)) if name == entry.section => x.add(totals),
+                    _ => sections.push((entry.section.to_owned(), totals)),
+                }
+            }
+            out.push(FileTotals {
+                path: path.clone(),
+                total,
+                sections,
+            });
+        }
+        out
+    }
+
+    /// lcov tracefile. Each condlist is a line, hit once per evaluation;
+    /// each statement a function, hit when chosen; each condition block two
+    /// branches, true and false.
+    pub fn to_lcov(&self) -> String {
+        let mut out = String::new();
+        for (path, file) in &self.files {
+            let Ok(ltx) = Ltx::from(&file.text) else {
+                continue;
+            };
+            let entries = Self::entries(&ltx, file);
+            out.push_str(&format!("TN:\nSF:{}\n", path.display()));
+
+            let mut functions = (0, 0);
+            for entry in &entries {
+                for i in 0..entry.ast.statements().len() {
+                    out.push_str(&format!("FN:{},{}\n", entry.line, entry.name(i)));
+                }
+            }
+            for entry in &entries {
+                for i in 0..entry.ast.statements().len() {
+                    out.push_str(&format!("FNDA:{},{}\n", entry.chosen(i), entry.name(i)));
+                    functions.1 += 1;
+                    functions.0 += (entry.chosen(i) > 0) as usize;
+                }
+            }
+            out.push_str(&format!("FNF:{}\nFNH:{}\n", functions.1, functions.0));
+
+            let mut branches = (0, 0);
+            for entry in &entries {
+                for i in 0..entry.ast.statements().len() {
+                    for j in 0..entry.conditions(i).len() {
+                        let hits = entry.branches(i, j);
+                        for (k, count) in hits.unwrap_or_default().into_iter().enumerate() {
+                            let taken = match hits {
+                                Some(_) => count.to_string(),
+                                None => "-".to_owned(),
+                            };
+                            out.push_str(&format!(
+                                "BRDA:{},{},{},{}\n",
+                                entry.line,
+                                i,
+                                j * 2 + k,
+                                taken
+                            ));
+                            branches.1 += 1;
+                            branches.0 += (count > 0) as usize;
+                        }
+                    }
+                }
+            }
+            out.push_str(&format!("BRF:{}\nBRH:{}\n", branches.1, branches.0));
+
+            let mut lines = (0, 0);
+            for entry in &entries {
+                out.push_str(&format!("DA:{},{}\n", entry.line, entry.hits.evaluations));
+                lines.1 += 1;
+                lines.0 += (entry.hits.evaluations > 0) as usize;
+            }
+            out.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", lines.1, lines.0));
+        }
+        out
+    }
+
+    /// A standalone page: totals by file and section, then every condlist
+    /// with uncovered statements and branches marked.
+    pub fn to_html(&self) -> String {
+        let percent = |(hit, total): (usize, usize)| match total {
+            0 => "-".to_owned(),
+            _ => format!("{}/{} ({}%)", hit, total, hit * 100 / total),
+        };
+
+        let mut out = String::from(HTML_HEAD);
+        out.push_str("<table>\n<tr><th></th><th>Statements</th><th>Branches</th></tr>\n");
+        for file in self.totals() {
+            out.push_str(&format!(
+                "<tr class=\"file\"><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape(&file.path.display().to_string()),
+                percent(file.total.statements),
+                percent(file.total.branches)
+            ));
+            for (name, x) in file.sections {
+                out.push_str(&format!(
+                    "<tr><td>[{}]</td><td>{}</td><td>{}</td></tr>\n",
+                    escape(&name),
+                    percent(x.statements),
+                    percent(x.branches)
+                ));
+            }
+        }
+        out.push_str("</table>\n");
+
+        for (path, file) in &self.files {
+            let Ok(ltx) = Ltx::from(&file.text) else {
+                continue;
+            };
+            out.push_str(&format!(
+                "<h2>{}</h2>\n",
+                escape(&path.display().to_string())
+            ));
+            let mut section = None;
+            for entry in Self::entries(&ltx, file) {
+                if section != Some(entry.section) {
+                    if section.is_some() {
+                        out.push_str("</pre>\n");
+                    }
+                    out.push_str(&format!("<h3>[{}]</h3>\n<pre>", escape(entry.section)));
+                    section = Some(entry.section);
+                }
+                out.push_str(&html_entry(&entry));
+            }
+            if section.is_some() {
+                out.push_str("</pre>\n");
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+const HTML_HEAD: &str = "\
+<!DOCTYPE html>
+<html>
+<head>
+<meta charset=\"utf-8\">
+<title>Condlist coverage</title>
+<style>
+.hit { background: #dfd; }
+.miss { background: #fdd; }
+.partial { background: #ffd; }
+tr.file { font-weight: bold; }
+</style>
+</head>
+<body>
+<h1>Condlist coverage</h1>
+";
+
+/// `line: key = statements`, statements and condition blocks with a class
+/// and a title giving their counts.
+fn html_entry(entry: &Entry) -> String {
+    let ast = &entry.ast;
+    let mut statements = Vec::new();
+    for (i, statement) in ast.statements().iter().enumerate() {
+        let mut parts = Vec::new();
+        if statement.conditions().is_some() {
+            let blocks = entry.conditions(i).iter().enumerate().map(|(j, block)| {
+                let [yes, no] = entry.branches(i, j).unwrap_or_default();
+                let class = match (yes > 0, no > 0) {
+                    (true, true) => "hit",
+                    (false, false) => "miss",
+                    _ => "partial",
+                };
+                format!(
+                    "<span class=\"{}\" title=\"true {}, false {}\">{}</span>",
+                    class,
+                    yes,
+                    no,
+                    escape(&ast.format_block(block))
+                )
+            });
+            parts.push(format!("{{{}}}", blocks.collect::<Vec<_>>().join(" ")));
+        }
+        if let Some(x) = statement.val() {
+            parts.push(escape(ast.slice_as_str(x)));
+        }
+        if let Some(x) = statement.effects() {
+            let blocks = x.blocks().iter().map(|b| escape(&ast.format_block(b)));
+            parts.push(format!("%{}%", blocks.collect::<Vec<_>>().join(" ")));
+        }
+        let chosen = entry.chosen(i);
+        statements.push(format!(
+            "<span class=\"{}\" title=\"chosen {}\">{}</span>",
+            if chosen > 0 { "hit" } else { "miss" },
+            chosen,
+            parts.join(" ")
+        ));
+    }
+    format!(
+        "{:>4}: {} = {}\n",
+        entry.line,
+        escape(entry.key),
+        statements.join(", ")
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::State;
+    use crate::trace::trace;
+
+    const LOGIC: &str = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+a =f} walker@2, {~0} walker@1
+path_walk = walk
+";
+
+    fn coverage() -> Coverage {
+        let path = Path::new("npc.ltx");
+        let mut coverage = Coverage::default();
+        coverage.add_file(path, LOGIC);
+        let ltx = Ltx::from(LOGIC).unwrap();
+        let condlist = ltx.condlist("walker@1", "on_info").unwrap();
+        let ast = ltx.parse(&condlist).unwrap();
+        let mut state = State::default();
+        state.stub("f", None, true);
+        for info in [false, true] {
+            if info {
+                state.infos.insert("a".to_owned());
+            }
+            let step = trace(&ast, &mut state).unwrap();
+            coverage.record(path, &condlist.value, &step);
+        }
+        coverage
+    }
+
+    #[test]
+    fn lcov() {
+        assert_eq!(
+            coverage().to_lcov(),
+            "\
+TN:
+SF:npc.ltx
+FN:2,[logic] active #1
+FN:5,[walker@1] on_info #1
+FN:5,[walker@1] on_info #2
+FNDA:0,[logic] active #1
+FNDA:1,[walker@1] on_info #1
+FNDA:0,[walker@1] on_info #2
+FNF:3
+FNH:1
+BRDA:5,0,0,1
+BRDA:5,0,1,1
+BRDA:5,0,2,1
+BRDA:5,0,3,0
+BRDA:5,1,0,0
+BRDA:5,1,1,1
+BRF:6
+BRH:4
+DA:2,0
+DA:5,2
+LF:2
+LH:1
+end_of_record
+"
+        );
+    }
+
+    #[test]
+    fn totals_and_html() {
+        let coverage = coverage();
+        let totals = coverage.totals();
+        assert_eq!(totals.len(), 1);
+        assert_eq!(totals[0].total.statements, (1, 3));
+        assert_eq!(totals[0].total.branches, (4, 6));
+        assert_eq!(
+            totals[0]
+                .sections
+                .iter()
+                .map(|x| x.0.as_str())
+                .collect::<Vec<_>>(),
+            vec!["logic", "walker@1"]
+        );
+
+        let html = coverage.to_html();
+        assert!(html.contains("<td>npc.ltx</td><td>1/3 (33%)</td><td>4/6 (66%)</td>"));
+        assert!(html.contains(
+            "   5: on_info = <span class=\"hit\" title=\"chosen 1\">{\
+             <span class=\"hit\" title=\"true 1, false 1\">+a</span> \
+             <span class=\"partial\" title=\"true 1, false 0\">=f</span>} walker@2</span>, \
+             <span class=\"miss\" title=\"chosen 0\">{\
+             <span class=\"partial\" title=\"true 0, false 1\">~0</span>} walker@1</span>"
+        ));
+    }
+}
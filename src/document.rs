@@ -0,0 +1,250 @@
+//! An LTX file kept parsed while it is edited. Condlists are single entry
+//! values and their trees are relative to the value, so an edit only reparses
+//! the values it touches; the others are reused, moved if needed.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::lint::{Diagnostic, lint_with};
+use crate::ltx::Ltx;
+use crate::parser::{Ast, OwnedAst, Slice};
+use crate::xref::InfoIndex;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Value {
+    len: usize,
+    ast: Result<OwnedAst, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    text: String,
+    /// Every condlist value by position.
+    values: BTreeMap<usize, Value>,
+}
+
+impl Document {
+    pub fn new(text: String) -> Self {
+        let mut out = Self {
+            text,
+            values: BTreeMap::new(),
+        };
+        out.reparse(|_| None);
+        out
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The sections of the file. Scanning them is cheap, condlists are not
+    /// parsed.
+    pub fn ltx(&self) -> Result<Ltx<'_>, String> {
+        Ltx::from(&self.text)
+    }
+
+    /// The parsed condlist at `value`, as found by [`Ltx::condlists`].
+    pub fn ast(&self, value: &Slice) -> Option<Result<&OwnedAst, &str>> {
+        let x = self.values.get(&value.index())?;
+        (x.len == value.len()).then(|| x.ast.as_ref().map_err(|e| e.as_str()))
+    }
+
+    /// Replaces `span` with `text`, reparsing the values it touches. Returns
+    /// the positions of the reparsed values in the new text.
+    pub fn edit(&mut self, span: Slice, text: &str) -> Vec<Slice> {
+        self.replace(span, text).1
+    }
+
+    /// [`Document::edit`] keeping what `index` knows about `file` up to date,
+    /// only the reparsed values are indexed again.
+    pub fn edit_indexed(&mut self, file: &Path, index: &mut InfoIndex, span: Slice, text: &str) {
+        let (dropped, parsed) = self.replace(span, text);
+        index.edit_file(file, span, text.len(), &dropped);
+        for value in parsed {
+            if let Some(Ok(ast)) = self.ast(&value) {
+                index.add_ast(file, ast, value.index());
+            }
+        }
+    }
+
+    /// [`Document::edit`], also returning the positions of the values that
+    /// were not reused in the old text.
+    fn replace(&mut self, span: Slice, text: &str) -> (Vec<Slice>, Vec<Slice>) {
+        self.text.replace_range(span.index()..span.end(), text);
+        let end = span.index() + text.len();
+
+        let mut old = std::mem::take(&mut self.values);
+        let mut dropped = Vec::new();
+        let parsed = self.reparse(|value| {
+            let ix = if value.end() <= span.index() {
+                value.index()
+            } else if value.index() >= end {
+                value.index() - end + span.end()
+            } else {
+                return None;
+            };
+            match old.remove(&ix) {
+                Some(x) if x.len == value.len() => Some(x),
+                Some(x) => {
+                    dropped.push(Slice::new(ix, x.len));
+                    None
+                }
+                None => None,
+            }
+        });
+        dropped.extend(old.iter().map(|(ix, x)| Slice::new(*ix, x.len)));
+        (dropped, parsed)
+    }
+
+    /// Fills `values`, reusing what `reuse` finds for a value, and returns
+    /// the values that had to be parsed.
+    fn reparse(&mut self, mut reuse: impl FnMut(&Slice) -> Option<Value>) -> Vec<Slice> {
+        let mut parsed = Vec::new();
+        // A file that does not scan has no condlists to keep.
+        let Ok(ltx) = Ltx::from(&self.text) else {
+            return parsed;
+        };
+        for condlist in ltx.condlists() {
+            let value = reuse(&condlist.value).unwrap_or_else(|| {
+                parsed.push(condlist.value);
+                Value {
+                    len: condlist.value.len(),
+                    ast: ltx.parse(&condlist).map(Ast::into_owned),
+                }
+            });
+            self.values.insert(condlist.value.index(), value);
+        }
+        parsed
+    }
+
+    /// [`crate::lint::lint_source`] without reparsing.
+    pub fn lint(&self) -> Vec<Diagnostic> {
+        let ltx = match self.ltx() {
+            Ok(x) => x,
+            Err(e) => return vec![Diagnostic::error(Slice::new(0, 0), "ltx-error", e)],
+        };
+        lint_with(&ltx, |x| match self.ast(&x.value) {
+            Some(Ok(ast)) => Ok(Cow::Borrowed(ast)),
+            Some(Err(e)) => Err(e.to_owned()),
+            None => ltx.parse(x).map(Cow::Owned),
+        })
+    }
+
+    /// Replaces what `index` knows about `file` with the references of this
+    /// document, see [`Document::edit_indexed`] to follow edits.
+    pub fn index(&self, file: &Path, index: &mut InfoIndex) {
+        index.remove_file(file);
+        for (ix, value) in &self.values {
+            if let Ok(ast) = &value.ast {
+                index.add_ast(file, ast, *ix);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::lint_source;
+
+    const LOGIC: &str = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+a} walker@2
+on_info2 = {+b} walker@1 %+c%
+
+[walker@2]
+on_info = {-a} walker@1
+";
+
+    fn edit(doc: &mut Document, needle: &str, text: &str) -> Vec<String> {
+        let ix = doc.text().find(needle).unwrap();
+        let parsed = doc.edit(Slice::new(ix, needle.len()), text);
+        assert_eq!(*doc, Document::new(doc.text().to_owned()));
+        parsed
+            .iter()
+            .map(|x| x.as_str(doc.text()).to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn reparses_touched_values() {
+        let mut doc = Document::new(LOGIC.to_owned());
+        assert_eq!(
+            edit(&mut doc, "+b", "+b =f(1)"),
+            vec!["{+b =f(1)} walker@1 %+c%"]
+        );
+        assert_eq!(
+            edit(&mut doc, "[walker@2]", "[walker@3]"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            edit(&mut doc, "walker@2\n", "walker@3\nx = y\n"),
+            vec!["{+a} walker@3", "y"]
+        );
+        assert_eq!(edit(&mut doc, "{-a}", "{{-a}"), vec!["{{-a} walker@1"]);
+        let value = Slice::new(doc.text().find("{{").unwrap(), 14);
+        assert!(doc.ast(&value).unwrap().is_err());
+    }
+
+    #[test]
+    fn lint_and_index() {
+        let mut doc = Document::new(LOGIC.to_owned());
+        edit(&mut doc, "walker@2\n", "walker@4\n");
+        assert_eq!(doc.lint(), lint_source(doc.text()));
+        assert_eq!(doc.lint()[0].code, "dangling-output");
+
+        let file = Path::new("a.ltx");
+        let mut index = InfoIndex::default();
+        index.add_ltx(file, &Ltx::from(LOGIC).unwrap());
+        edit(&mut doc, "%+c%", "%+d%");
+        doc.index(file, &mut index);
+
+        let mut fresh = InfoIndex::default();
+        fresh.add_ltx(file, &doc.ltx().unwrap());
+        assert_eq!(index.keys().collect::<Vec<_>>(), vec!["a", "b", "d"]);
+        for key in ["a", "b", "d"] {
+            assert_eq!(index.references(key), fresh.references(key));
+        }
+    }
+
+    #[test]
+    fn edit_indexed() {
+        let (file, other) = (Path::new("a.ltx"), Path::new("b.ltx"));
+        let mut doc = Document::new(LOGIC.to_owned());
+        let mut index = InfoIndex::default();
+        index.add_ltx(other, &Ltx::from(LOGIC).unwrap());
+        doc.index(file, &mut index);
+
+        let edits = [
+            ("%+c%", "%+d%"),
+            ("{+a}", "{+e +a}"),
+            ("walker@2\n", "walker@2\nx = {-f} y\n"),
+            ("[walker@1]\non_info = {+e +a} walker@2\n", ""),
+            ("x = {-f", "x = {-g"),
+        ];
+        for (needle, text) in edits {
+            let ix = doc.text().find(needle).unwrap();
+            doc.edit_indexed(file, &mut index, Slice::new(ix, needle.len()), text);
+
+            let mut fresh = InfoIndex::default();
+            fresh.add_ltx(other, &Ltx::from(LOGIC).unwrap());
+            fresh.add_ltx(file, &doc.ltx().unwrap());
+            assert_eq!(
+                index.keys().collect::<Vec<_>>(),
+                fresh.keys().collect::<Vec<_>>()
+            );
+            let sorted = |index: &InfoIndex, key| {
+                let mut out = index.references(key).to_vec();
+                out.sort_by_key(|x| (x.file.clone(), x.span.index()));
+                out
+            };
+            for key in fresh.keys() {
+                assert_eq!(sorted(&index, key), sorted(&fresh, key), "{}", needle);
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::logic::{Atom, Conjunction, Literal, chance};
+use crate::order::impure;
+use crate::parser::Ast;
+
+/// More atoms than this make the check too slow to be useful.
+const MAX_ATOMS: usize = 20;
+
+/// What an evaluation does: the chosen output and the effects it runs.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Behaviour {
+    pub output: Option<String>,
+    pub effects: Vec<String>,
+    /// Condition calls with side effects that ran, in order, see
+    /// [`crate::order::impure`].
+    pub calls: Vec<String>,
+}
+
+impl fmt::Display for Behaviour {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.output.as_deref().unwrap_or("nil"))?;
+        if !self.effects.is_empty() {
+            write!(f, " %{}%", self.effects.join(" "))?;
+        }
+        if !self.calls.is_empty() {
+            write!(f, " after {}", self.calls.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A condition block of a [`Model`] statement.
+enum Check {
+    /// With whether the block is a call with side effects.
+    Literal(Literal, bool),
+    Chance(u32),
+}
+
+/// A world where two condlists behave differently. Behaviours come with
+/// their probability in percent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    pub world: BTreeMap<Atom, bool>,
+    pub left: Vec<(Behaviour, u32)>,
+    pub right: Vec<(Behaviour, u32)>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let world = self
+            .world
+            .iter()
+            .map(|(atom, value)| format!("{} = {}", atom, value))
+            .collect::<Vec<_>>();
+        writeln!(f, "when {}:", world.join(", "))?;
+        for (name, side) in [("left", &self.left), ("right", &self.right)] {
+            let behaviours = side
+                .iter()
+                .map(|(x, p)| format!("{} ({}%)", x, p))
+                .collect::<Vec<_>>();
+            writeln!(f, "  {}: {}", name, behaviours.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// A condlist reduced to what `equivalent` compares.
+pub(crate) struct Model {
+    pub statements: Vec<(Conjunction, Behaviour)>,
+    /// The condition blocks of every statement, in the order they are
+    /// checked.
+    checks: Vec<Vec<Check>>,
+}
+
+impl Model {
+    pub fn new(ast: &Ast) -> Self {
+        let statements = ast
+            .statements()
+            .iter()
+            .map(|x| {
+                let behaviour = Behaviour {
+                    output: x.val().map(|v| ast.slice_as_str(v).to_owned()),
+                    effects: x
+                        .effects()
+                        .map(|e| e.blocks())
+                        .unwrap_or(&[])
+                        .iter()
+                        .map(|b| ast.format_block(b))
+                        .collect(),
+                    calls: Vec::new(),
+                };
+                (Conjunction::from_statement(ast, x), behaviour)
+            })
+            .collect();
+        let checks =
+            ast.statements()
+                .iter()
+                .map(|x| {
+                    let blocks = x.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+                    blocks
+                        .iter()
+                        .filter_map(|b| match chance(ast, b) {
+                            Some(x) => Some(Check::Chance(x)),
+                            None => Literal::from_block(ast, b)
+                                .map(|x| Check::Literal(x, impure(ast, b))),
+                        })
+                        .collect()
+                })
+                .collect();
+        Self { statements, checks }
+    }
+
+    /// Checks the blocks in order like [`crate::eval::evaluate`], noting the
+    /// calls with side effects that run on the way.
+    fn behaviour(&self, world: &BTreeMap<Atom, bool>, roll: u32) -> Behaviour {
+        let mut calls = Vec::new();
+        for ((_, behaviour), checks) in self.statements.iter().zip(&self.checks) {
+            let holds = checks.iter().all(|x| match x {
+                Check::Literal(literal, impure) => {
+                    if *impure {
+                        calls.push(literal.atom.to_string());
+                    }
+                    world.get(&literal.atom).copied().unwrap_or(false) == literal.value
+                }
+                Check::Chance(x) => roll <= *x,
+            });
+            if holds {
+                return Behaviour {
+                    calls,
+                    ..behaviour.clone()
+                };
+            }
+        }
+        Behaviour {
+            output: None,
+            effects: Vec::new(),
+            calls,
+        }
+    }
+
+    /// Probability in percent of every behaviour, given the split of rolls
+    /// into ranges that no chance of either condlist separates.
+    pub fn distribution(
+        &self,
+        world: &BTreeMap<Atom, bool>,
+        ranges: &[(u32, u32)],
+    ) -> Vec<(Behaviour, u32)> {
+        let mut out: BTreeMap<Behaviour, u32> = BTreeMap::new();
+        for (roll, weight) in ranges {
+            *out.entry(self.behaviour(world, *roll)).or_default() += weight;
+        }
+        out.into_iter().collect()
+    }
+}
+
+/// Distinct atoms of the given conditions, at most [`MAX_ATOMS`].
+pub(crate) fn atoms<'c>(
+    conditions: impl Iterator<Item = &'c Conjunction>,
+) -> Result<Vec<Atom>, String> {
+    let atoms = conditions
+        .flat_map(|x| x.literals.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    if atoms.len() > MAX_ATOMS {
+        return Err(format!(
+            "{} distinct atoms, at most {} can be checked",
+            atoms.len(),
+            MAX_ATOMS
+        ));
+    }
+    Ok(atoms.into_iter().collect())
+}
+
+/// Splits the rolls into `(highest, count)` ranges that no chance of the given
+/// conditions separates: every roll in `previous + 1..=highest` gives the same
+/// results.
+pub(crate) fn roll_ranges<'c>(
+    conditions: impl Iterator<Item = &'c Conjunction>,
+) -> Vec<(u32, u32)> {
+    let thresholds = conditions
+        .filter_map(|x| x.chance)
+        .chain([100])
+        .collect::<BTreeSet<_>>();
+    let mut previous = 0;
+    thresholds
+        .into_iter()
+        .map(|x| {
+            let range = (x, x - previous);
+            previous = x;
+            range
+        })
+        .collect()
+}
+
+/// Decides whether `left` and `right` choose the same output and run the same
+/// effects for every assignment of info portions and call results, with the
+/// same probabilities. Call results are opaque, and `~N` checks share one roll
+/// as in [`crate::eval::evaluate`]. Conditions with side effects must also
+/// run the same way, so the blocks before them matter.
+///
+/// Returns a world where they differ, or `None` when they are equivalent.
+pub fn equivalent(left: &Ast, right: &Ast) -> Result<Option<Counterexample>, String> {
+    let models = [Model::new(left), Model::new(right)];
+    let conditions = || {
+        models
+            .iter()
+            .flat_map(|x| x.statements.iter().map(|(c, _)| c))
+    };
+
+    let atoms = atoms(conditions())?;
+    let ranges = roll_ranges(conditions());
+
+    for bits in 0..1u32 << atoms.len() {
+        let world = atoms
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.clone(), bits & (1 << i) != 0))
+            .collect::<BTreeMap<_, _>>();
+        let [a, b] = [&models[0], &models[1]].map(|x| x.distribution(&world, &ranges));
+        if a != b {
+            return Ok(Some(Counterexample {
+                world,
+                left: a,
+                right: b,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(left: &str, right: &str) -> Option<Counterexample> {
+        equivalent(&Ast::from(left).unwrap(), &Ast::from(right).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn equivalent_rewrites() {
+        assert_eq!(check("{+a +b} X, Y", "{+b +a} X, Y"), None);
+        assert_eq!(check("{+a} X, {+a +b} Z, Y", "{+a} X, Y"), None);
+        assert_eq!(check("{+a} X, {-a} Y", "{+a} X, Y"), None);
+        assert_eq!(check("{~30 ~50} X, Y", "{~30} X, {} Y"), None);
+        assert_eq!(check("{+a -a} X, Y %+b%", "Y %+b%"), None);
+        assert_eq!(check("{=f(1)} X %=g%, Y", "{!f(1)} Y, X %=g%"), None);
+    }
+
+    #[test]
+    fn different_output() {
+        let x = check("{+a} X, Y", "{+a} X, Z").unwrap();
+        assert_eq!(x.world.get(&Atom::Info("a".to_owned())), Some(&false));
+        assert_eq!(x.left[0].0.output.as_deref(), Some("Y"));
+        assert_eq!(x.right[0].0.output.as_deref(), Some("Z"));
+    }
+
+    #[test]
+    fn different_effects() {
+        let x = check("{+a} X %+b%", "{+a} X %+c%").unwrap();
+        assert_eq!(x.left[0].0.effects, vec!["+b"]);
+        assert_eq!(
+            x.to_string(),
+            "when +a = true:\n  left: X %+b% (100%)\n  right: X %+c% (100%)\n"
+        );
+    }
+
+    #[test]
+    fn different_chance() {
+        let x = check("{~30} X, Y", "{~40} X, Y").unwrap();
+        assert_eq!(x.left[0].1, 30);
+        assert_eq!(x.right[0].1, 40);
+    }
+
+    #[test]
+    fn order_matters() {
+        assert!(check("{+a} X, {+b} Y", "{+b} Y, {+a} X").is_some());
+    }
+
+    #[test]
+    fn side_effects() {
+        let x = check("{=random_choice(50) +a} X", "{+a =random_choice(50)} X").unwrap();
+        assert_eq!(x.world.get(&Atom::Info("a".to_owned())), Some(&false));
+        assert_eq!(x.left[0].0.to_string(), "nil after =random_choice(50)");
+        assert_eq!(x.right[0].0.to_string(), "nil");
+        // Pure calls may still move.
+        assert_eq!(check("{=is_day +a} X", "{+a =is_day} X"), None);
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::order::{Op, chance_passes, ops};
+use crate::parser::{Ast, Block};
+
+/// Everything a condlist can read or change while it is evaluated.
+pub trait World {
+    fn has_info(&self, key: &str) -> bool;
+    fn give_info(&mut self, key: &str);
+    fn disable_info(&mut self, key: &str);
+    /// Result of `xr_conditions.<function>`.
+    fn condition(&mut self, function: &str, args: &[&str]) -> Result<bool, String>;
+    /// Runs `xr_effects.<function>`.
+    fn effect(&mut self, function: &str, args: &[&str]) -> Result<(), String>;
+    /// Random number in `1..=100` for `~N` checks.
+    fn roll(&mut self) -> u32;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    /// Index of the chosen statement.
+    pub statement: Option<usize>,
+    pub output: Option<String>,
+}
+
+/// Picks the first statement whose conditions hold and runs its effects, in
+/// the order described in [`crate::order`].
+pub fn evaluate(ast: &Ast, world: &mut impl World) -> Result<Outcome, String> {
+    evaluate_with(ast, world, |_, _, _| {})
+}
+
+/// [`evaluate`], calling `observe` with the statement index, block index and
+/// result of every condition block checked.
+pub fn evaluate_with(
+    ast: &Ast,
+    world: &mut impl World,
+    mut observe: impl FnMut(usize, usize, bool),
+) -> Result<Outcome, String> {
+    let mut roll = None;
+
+    'statements: for (i, statement) in ast.statements().iter().enumerate() {
+        for op in ops(statement) {
+            match op {
+                Op::Check(j, block) => {
+                    let value = match block {
+                        Block::InfoPortion { key, inverted } => {
+                            world.has_info(ast.slice_as_str(key)) != *inverted
+                        }
+                        Block::Call {
+                            function,
+                            args,
+                            inverted,
+                        } => {
+                            let args = args.iter().map(|x| ast.slice_as_str(x)).collect::<Vec<_>>();
+                            world.condition(ast.slice_as_str(function), &args)? != *inverted
+                        }
+                        Block::Chance { val } => {
+                            let chance = ast
+                                .slice_as_str(val)
+                                .parse::<u32>()
+                                .map_err(|e| e.to_string())?;
+                            chance_passes(*roll.get_or_insert_with(|| world.roll()), chance)
+                        }
+                    };
+                    observe(i, j, value);
+                    if !value {
+                        continue 'statements;
+                    }
+                }
+                Op::Run(block) => match block {
+                    Block::InfoPortion {
+                        key,
+                        inverted: false,
+                    } => world.give_info(ast.slice_as_str(key)),
+                    Block::InfoPortion {
+                        key,
+                        inverted: true,
+                    } => world.disable_info(ast.slice_as_str(key)),
+                    Block::Call { function, args, .. } => {
+                        let args = args.iter().map(|x| ast.slice_as_str(x)).collect::<Vec<_>>();
+                        world.effect(ast.slice_as_str(function), &args)?
+                    }
+                    Block::Chance { .. } => return Err("Chance in effects".to_owned()),
+                },
+                Op::Return => {
+                    return Ok(Outcome {
+                        statement: Some(i),
+                        output: statement.val().map(|x| ast.slice_as_str(x).to_owned()),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(Outcome {
+        statement: None,
+        output: None,
+    })
+}
+
+/// A simulated world: a set of info portions, stubbed condition results and a
+/// seeded random generator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    pub infos: BTreeSet<String>,
+    /// Condition results by function and arguments. `None` arguments match
+    /// any call of the function.
+    stubs: BTreeMap<(String, Option<Vec<String>>), bool>,
+    /// `xr_effects` calls made so far.
+    pub calls: Vec<(String, Vec<String>)>,
+    rng: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+impl State {
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            infos: Default::default(),
+            stubs: Default::default(),
+            calls: Default::default(),
+            // xorshift gets stuck on zero
+            rng: seed ^ 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Self::with_seed(seed).rng;
+    }
+
+    /// Makes `function(args)` return `value`, or every call of `function`
+    /// when `args` is `None`.
+    pub fn stub(&mut self, function: &str, args: Option<&[&str]>, value: bool) {
+        let args = args.map(|x| x.iter().map(|a| a.to_string()).collect());
+        self.stubs.insert((function.to_owned(), args), value);
+    }
+
+    /// [`State::stub`] from `F=BOOL`, `F(A:B)=BOOL` or `F A B = BOOL`.
+    pub fn stub_str(&mut self, spec: &str) -> Result<(), String> {
+        let (call, value) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("{}: expected F=BOOL", spec))?;
+        let value = value
+            .trim()
+            .parse::<bool>()
+            .map_err(|e| format!("{}: {}", spec, e))?;
+        let (function, args) = match call.split_once('(') {
+            Some((function, rest)) => {
+                let args = rest.trim().trim_end_matches(')').split(':').collect();
+                (function.trim(), Some(args))
+            }
+            None => {
+                let mut words = call.split_whitespace();
+                let function = words
+                    .next()
+                    .ok_or_else(|| format!("{}: missing function", spec))?;
+                let args = words.collect::<Vec<_>>();
+                (function, (!args.is_empty()).then_some(args))
+            }
+        };
+        self.stub(function, args.as_deref(), value);
+        Ok(())
+    }
+
+    /// Stubbed condition results, see [`State::stub`].
+    pub fn stubs(&self) -> impl Iterator<Item = (&str, Option<&[String]>, bool)> {
+        self.stubs
+            .iter()
+            .map(|((f, args), value)| (f.as_str(), args.as_deref(), *value))
+    }
+}
+
+impl World for State {
+    fn has_info(&self, key: &str) -> bool {
+        self.infos.contains(key)
+    }
+
+    fn give_info(&mut self, key: &str) {
+        self.infos.insert(key.to_owned());
+    }
+
+    fn disable_info(&mut self, key: &str) {
+        self.infos.remove(key);
+    }
+
+    fn condition(&mut self, function: &str, args: &[&str]) -> Result<bool, String> {
+        let exact = (
+            function.to_owned(),
+            Some(args.iter().map(|x| x.to_string()).collect()),
+        );
+        self.stubs
+            .get(&exact)
+            .or_else(|| self.stubs.get(&(function.to_owned(), None)))
+            .copied()
+            .ok_or_else(|| format!("No stub for condition `{}({})`", function, args.join(":")))
+    }
+
+    fn effect(&mut self, function: &str, args: &[&str]) -> Result<(), String> {
+        self.calls.push((
+            function.to_owned(),
+            args.iter().map(|x| x.to_string()).collect(),
+        ));
+        Ok(())
+    }
+
+    fn roll(&mut self) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng % 100) as u32 + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_passing_statement() {
+        let ast = Ast::from("{+a} X, {-b =f(1)} Y %+b =g(2:3)%, Z").unwrap();
+        let mut state = State::default();
+        state.stub("f", None, true);
+
+        let outcome = evaluate(&ast, &mut state).unwrap();
+        assert_eq!(outcome.statement, Some(1));
+        assert_eq!(outcome.output.as_deref(), Some("Y"));
+        assert!(state.has_info("b"));
+        assert_eq!(
+            state.calls,
+            vec![("g".to_owned(), vec!["2".to_owned(), "3".to_owned()])]
+        );
+
+        let outcome = evaluate(&ast, &mut state).unwrap();
+        assert_eq!(outcome.output.as_deref(), Some("Z"));
+    }
+
+    #[test]
+    fn short_circuit() {
+        // `f` has no stub, but it is never reached.
+        let ast = Ast::from("{+a =f} X, Y").unwrap();
+        let outcome = evaluate(&ast, &mut State::default()).unwrap();
+        assert_eq!(outcome.output.as_deref(), Some("Y"));
+
+        let ast = Ast::from("{=f} X, Y").unwrap();
+        assert!(evaluate(&ast, &mut State::default()).is_err());
+    }
+
+    #[test]
+    fn observed_blocks() {
+        let ast = Ast::from("{+a =f} X, {-a !f ~100} Y").unwrap();
+        let mut state = State::default();
+        state.stub("f", None, false);
+        let mut checked = Vec::new();
+        evaluate_with(&ast, &mut state, |i, j, value| checked.push((i, j, value))).unwrap();
+        assert_eq!(
+            checked,
+            vec![(0, 0, false), (1, 0, true), (1, 1, true), (1, 2, true)]
+        );
+    }
+
+    #[test]
+    fn exact_stub() {
+        let ast = Ast::from("{=is_alive(wolf)} X, Y").unwrap();
+        let mut state = State::default();
+        state.stub("is_alive", None, true);
+        state.stub("is_alive", Some(&["wolf"]), false);
+        assert_eq!(evaluate(&ast, &mut state).unwrap().statement, Some(1));
+    }
+
+    #[test]
+    fn stub_syntax() {
+        let mut state = State::default();
+        state.stub_str("f=true").unwrap();
+        state.stub_str("is_alive(wolf)=false").unwrap();
+        state.stub_str("npc_in_zone wolf zone = true").unwrap();
+        assert_eq!(state.condition("f", &["x"]), Ok(true));
+        assert_eq!(state.condition("is_alive", &["wolf"]), Ok(false));
+        assert!(state.condition("is_alive", &["dog"]).is_err());
+        assert_eq!(state.condition("npc_in_zone", &["wolf", "zone"]), Ok(true));
+        assert!(state.stub_str("f").is_err());
+        assert!(state.stub_str("f=maybe").is_err());
+    }
+
+    #[test]
+    fn chance() {
+        let ast = Ast::from("{~0} X, {~100} Y").unwrap();
+        let outcome = evaluate(&ast, &mut State::with_seed(42)).unwrap();
+        assert_eq!(outcome.output.as_deref(), Some("Y"));
+    }
+
+    #[test]
+    fn nothing_passes() {
+        let ast = Ast::from("{+a} X").unwrap();
+        let outcome = evaluate(&ast, &mut State::default()).unwrap();
+        assert_eq!(
+            outcome,
+            Outcome {
+                statement: None,
+                output: None
+            }
+        );
+    }
+}
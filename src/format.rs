@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::parser::{Ast, Block, Statement};
+
+impl Ast<'_> {
+    /// Canonical text of a block, e.g. `=is_alive(wolf)` or `-done`.
+    pub fn format_block(&self, block: &Block) -> String {
+        match block {
+            Block::InfoPortion { key, inverted } => {
+                format!(
+                    "{}{}",
+                    if *inverted { '-' } else { '+' },
+                    self.slice_as_str(key)
+                )
+            }
+            Block::Call {
+                function,
+                args,
+                inverted,
+            } => {
+                let mut out = format!(
+                    "{}{}",
+                    if *inverted { '!' } else { '=' },
+                    self.slice_as_str(function)
+                );
+                if !args.is_empty() {
+                    out.push('(');
+                    out.push_str(
+                        &args
+                            .iter()
+                            .map(|x| self.slice_as_str(x))
+                            .collect::<Vec<_>>()
+                            .join(":"),
+                    );
+                    out.push(')');
+                }
+                out
+            }
+            Block::Chance { val } => format!("~{}", self.slice_as_str(val)),
+        }
+    }
+
+    /// Canonical text of a statement: `{conditions} output %effects%`.
+    pub fn format_statement(&self, statement: &Statement) -> String {
+        let mut parts = Vec::new();
+        if let Some(x) = statement.conditions() {
+            let blocks = x.blocks().iter().map(|b| self.format_block(b));
+            parts.push(format!("{{{}}}", blocks.collect::<Vec<_>>().join(" ")));
+        }
+        if let Some(x) = statement.val() {
+            parts.push(self.slice_as_str(x).to_owned());
+        }
+        if let Some(x) = statement.effects() {
+            let blocks = x.blocks().iter().map(|b| self.format_block(b));
+            parts.push(format!("%{}%", blocks.collect::<Vec<_>>().join(" ")));
+        }
+        parts.join(" ")
+    }
+}
+
+impl fmt::Display for Ast<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, statement) in self.statements().iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(&self.format_statement(statement))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical() {
+        let ast = Ast::from("{=A(a1:a2)   !B +C\t-D ~30}X%=E(e1) +F -G%,Y").unwrap();
+        assert_eq!(
+            ast.to_string(),
+            "{=A(a1:a2) !B +C -D ~30} X %=E(e1) +F -G%, Y"
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let src = "{+a} walker@1 %+b%, {} nil, walker@2 %=f%, true";
+        let ast = Ast::from(src).unwrap();
+        assert_eq!(ast.to_string(), src);
+        assert_eq!(Ast::from(&ast.to_string()).unwrap().to_string(), src);
+    }
+}
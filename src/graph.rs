@@ -0,0 +1,511 @@
+//! The state machine of a logic scheme: sections are nodes, and every
+//! statement of a transition condlist (see [`Ltx::transitions`]) choosing a
+//! section is an edge.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+use crate::lint::SPECIAL_OUTPUTS;
+use crate::logic::{Atom, Conjunction};
+use crate::ltx::{Condlist, Ltx, is_transition};
+use crate::parser::Ast;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub name: String,
+    /// Whether the file has a section with this name.
+    pub defined: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    /// Key of the transition entry, e.g. `on_info`.
+    pub key: String,
+    pub condition: Conjunction,
+    /// Condition blocks as written.
+    pub blocks: Vec<String>,
+    pub effects: Vec<String>,
+}
+
+impl Edge {
+    /// `key {blocks} %effects%`, as shown on the edge.
+    pub fn label(&self) -> String {
+        let mut out = self.key.clone();
+        if !self.blocks.is_empty() {
+            out.push_str(&format!(" {{{}}}", self.blocks.join(" ")));
+        }
+        if !self.effects.is_empty() {
+            out.push_str(&format!(" %{}%", self.effects.join(" ")));
+        }
+        out
+    }
+}
+
+/// What [`Graph::reachability`] found. Every list holds node indices.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reachability {
+    /// Sections the scheme can switch to or use, starting from `active` and
+    /// the `on_*` entries of `[logic]`.
+    pub reachable: Vec<usize>,
+    /// Logic sections that are never switched to.
+    pub dead: Vec<usize>,
+    /// Groups of sections that, once entered, switch between each other
+    /// forever.
+    pub loops: Vec<Vec<usize>>,
+    /// Sections switched to that have no transition to another section or
+    /// ending the logic.
+    pub no_exit: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Graph {
+    /// Sections in the order they are first seen.
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+    /// Sections named by other entries of a section, e.g. `meet@guard` by
+    /// `meet = meet@guard`: the scheme uses them without switching to them.
+    pub links: Vec<(usize, usize)>,
+    /// Sections with a transition choosing a special output such as `nil`,
+    /// which ends the logic instead of switching.
+    pub ends: BTreeSet<usize>,
+}
+
+impl Graph {
+    /// Fails on the first transition that does not parse.
+    pub fn from_ltx(ltx: &Ltx) -> Result<Self, String> {
+        Self::from_ltx_with(ltx, |x| ltx.parse(x).map(Cow::Owned))
+    }
+
+    /// [`Graph::from_ltx`] with condlists parsed by `parse`, which may return
+    /// cached trees.
+    pub(crate) fn from_ltx_with<'a>(
+        ltx: &Ltx<'a>,
+        parse: impl Fn(&Condlist) -> Result<Cow<'a, Ast<'a>>, String>,
+    ) -> Result<Self, String> {
+        let mut out = Self::default();
+        for section in ltx.sections() {
+            out.node(ltx, ltx.slice_as_str(section.name()));
+        }
+
+        for condlist in ltx.transitions() {
+            let from = out.node(ltx, ltx.slice_as_str(condlist.section.name()));
+            let key = ltx.slice_as_str(condlist.entry.key());
+            let ast = parse(&condlist)
+                .map_err(|e| format!("[{}] {}: {}", out.nodes[from].name, key, e))?;
+
+            for statement in ast.statements() {
+                let Some(name) = statement.val().map(|x| ast.slice_as_str(x)) else {
+                    continue;
+                };
+                if SPECIAL_OUTPUTS.contains(&name) {
+                    out.ends.insert(from);
+                    continue;
+                }
+                let to = out.node(ltx, name);
+                out.edges.push(Edge {
+                    from,
+                    to,
+                    key: key.to_owned(),
+                    condition: Conjunction::from_statement(&ast, statement),
+                    blocks: statement
+                        .conditions()
+                        .map(|x| x.blocks())
+                        .unwrap_or(&[])
+                        .iter()
+                        .map(|b| ast.format_block(b))
+                        .collect(),
+                    effects: statement
+                        .effects()
+                        .map(|x| x.blocks())
+                        .unwrap_or(&[])
+                        .iter()
+                        .map(|b| ast.format_block(b))
+                        .collect(),
+                });
+            }
+        }
+
+        for section in ltx.sections() {
+            let from = out.node(ltx, ltx.slice_as_str(section.name()));
+            for entry in section.entries() {
+                let Some(value) = entry.value() else {
+                    continue;
+                };
+                if is_transition(ltx.slice_as_str(entry.key())) {
+                    continue;
+                }
+                let words = ltx
+                    .slice_as_str(value)
+                    .split(|x: char| !(x.is_alphanumeric() || x == '_' || x == '@'))
+                    .filter(|x| ltx.section(x).is_some());
+                for word in words {
+                    let to = out.node(ltx, word);
+                    if to != from && !out.links.contains(&(from, to)) {
+                        out.links.push((from, to));
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    /// Index of the node named `name`, added if missing.
+    fn node(&mut self, ltx: &Ltx, name: &str) -> usize {
+        if let Some(ix) = self.nodes.iter().position(|x| x.name == name) {
+            return ix;
+        }
+        self.nodes.push(Node {
+            name: name.to_owned(),
+            defined: ltx.section(name).is_some(),
+        });
+        self.nodes.len() - 1
+    }
+
+    /// Sections without transitions are left out, unless something leads to
+    /// them. Undefined sections are drawn dashed.
+    pub fn to_dot(&self) -> String {
+        let quote = |x: &str| format!("\"{}\"", x.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut out = String::from("digraph {\n");
+        for (ix, node) in self.nodes.iter().enumerate() {
+            if !self.is_connected(ix) {
+                continue;
+            }
+            out.push_str(&format!("    {}", quote(&node.name)));
+            if !node.defined {
+                out.push_str(" [style=dashed]");
+            }
+            out.push_str(";\n");
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "    {} -> {} [label={}];\n",
+                quote(&self.nodes[edge.from].name),
+                quote(&self.nodes[edge.to].name),
+                quote(&edge.label())
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// A `flowchart`. Nodes are named `n0`, `n1`... since section names are
+    /// not valid Mermaid identifiers. Undefined sections are drawn round.
+    pub fn to_mermaid(&self) -> String {
+        let quote = |x: &str| format!("\"{}\"", x.replace('"', "#quot;"));
+        let mut out = String::from("flowchart LR\n");
+        for (ix, node) in self.nodes.iter().enumerate() {
+            if !self.is_connected(ix) {
+                continue;
+            }
+            let (open, close) = if node.defined { ("[", "]") } else { ("(", ")") };
+            out.push_str(&format!(
+                "    n{}{}{}{}\n",
+                ix,
+                open,
+                quote(&node.name),
+                close
+            ));
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "    n{} -->|{}| n{}\n",
+                edge.from,
+                quote(&edge.label()),
+                edge.to
+            ));
+        }
+        out
+    }
+
+    /// Follows the transitions and [`Graph::links`] from the targets of
+    /// `active` and of the `on_*` entries of `[logic]`, such as `on_hit`.
+    /// Edges that can never be taken are ignored, and so are edges back to
+    /// the same section, since switching to the active section does nothing.
+    ///
+    /// A loop is reported when its transitions have no effects, only check
+    /// info portions and can all be taken in the same world: nothing in the
+    /// loop changes the info portions, so it never ends. Sections are logic
+    /// sections when their name has a `@` or something switches to them.
+    pub fn reachability(&self) -> Reachability {
+        let live = |x: &&Edge| !x.condition.contradictory && x.from != x.to;
+        let start = self
+            .edges
+            .iter()
+            .filter(|x| x.key == "active" || self.nodes[x.from].name == "logic")
+            .filter(live)
+            .map(|x| x.to);
+        let reachable = self.reach(start, |x| live(&x), true);
+
+        let targets = self.edges.iter().map(|x| x.to).collect::<BTreeSet<_>>();
+        let dead = (0..self.nodes.len())
+            .filter(|x| !reachable.contains(x))
+            .filter(|x| {
+                let node = &self.nodes[*x];
+                node.defined && (node.name.contains('@') || targets.contains(x))
+            })
+            .collect();
+
+        let is_static = |x: &Edge| {
+            live(&x)
+                && x.effects.is_empty()
+                && x.condition.chance.is_none()
+                && x.condition
+                    .literals
+                    .keys()
+                    .all(|a| matches!(a, Atom::Info(_)))
+        };
+        let mut loops: Vec<Vec<usize>> = Vec::new();
+        for node in 0..self.nodes.len() {
+            if loops.iter().any(|x| x.contains(&node)) || !self.cycles(node, is_static) {
+                continue;
+            }
+            let component = self
+                .reach([node], is_static, false)
+                .into_iter()
+                .filter(|x| *x == node || self.reach([*x], is_static, false).contains(&node))
+                .filter(|x| self.cycles(*x, is_static))
+                .collect::<Vec<_>>();
+            loops.push(component);
+        }
+
+        // Sections only used, or entered on events such as `on_hit`, are not
+        // expected to switch anywhere.
+        let switched = self
+            .edges
+            .iter()
+            .filter(live)
+            .filter(|x| x.key == "active" || reachable.contains(&x.from))
+            .map(|x| x.to)
+            .collect::<BTreeSet<_>>();
+        let no_exit = switched
+            .iter()
+            .copied()
+            .filter(|x| reachable.contains(x) && self.nodes[*x].defined)
+            .filter(|x| !self.ends.contains(x))
+            .filter(|x| !self.edges.iter().filter(live).any(|e| e.from == *x))
+            .collect();
+
+        Reachability {
+            reachable: reachable.into_iter().collect(),
+            dead,
+            loops,
+            no_exit,
+        }
+    }
+
+    /// Nodes reachable from `start` over edges accepted by `follow`, and
+    /// over links when `links` is set, including `start`, excluding edges
+    /// back to the same node.
+    fn reach(
+        &self,
+        start: impl IntoIterator<Item = usize>,
+        follow: impl Fn(&Edge) -> bool,
+        links: bool,
+    ) -> BTreeSet<usize> {
+        let mut seen = BTreeSet::new();
+        let mut stack = start.into_iter().collect::<Vec<_>>();
+        while let Some(node) = stack.pop() {
+            if !seen.insert(node) {
+                continue;
+            }
+            stack.extend(
+                self.edges
+                    .iter()
+                    .filter(|x| x.from == node && x.from != x.to && follow(x))
+                    .map(|x| x.to),
+            );
+            if links {
+                stack.extend(self.links.iter().filter(|x| x.0 == node).map(|x| x.1));
+            }
+        }
+        seen
+    }
+
+    /// Whether some cycle through `node` over edges accepted by `follow` has
+    /// conditions that can hold together, so one world takes all its edges.
+    fn cycles(&self, node: usize, follow: impl Fn(&Edge) -> bool) -> bool {
+        let mut stack = vec![(vec![node], Conjunction::default())];
+        while let Some((path, condition)) = stack.pop() {
+            let at = *path.last().expect("never empty");
+            let edges = self
+                .edges
+                .iter()
+                .filter(|x| x.from == at && x.from != x.to && follow(x));
+            for edge in edges {
+                let condition = condition.and(&edge.condition);
+                if condition.contradictory {
+                    continue;
+                }
+                if edge.to == node {
+                    return true;
+                }
+                if !path.contains(&edge.to) {
+                    let mut path = path.clone();
+                    path.push(edge.to);
+                    stack.push((path, condition));
+                }
+            }
+        }
+        false
+    }
+
+    fn is_connected(&self, node: usize) -> bool {
+        self.edges.iter().any(|x| x.from == node || x.to == node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIC: &str = "\
+[logic]
+active = walker@guard
+
+[walker@guard]
+path_walk = guard_walk
+on_info = {+esc_done} walker@2 %+x%, {=is_day} nil
+on_actor_dist_le = 5 | remark@end
+
+[walker@2]
+on_timer = 1000 | walker@guard
+";
+
+    #[test]
+    fn edges() {
+        let ltx = Ltx::from(LOGIC).unwrap();
+        let graph = Graph::from_ltx(&ltx).unwrap();
+        let edges = graph
+            .edges
+            .iter()
+            .map(|x| {
+                (
+                    graph.nodes[x.from].name.as_str(),
+                    graph.nodes[x.to].name.as_str(),
+                    x.label(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            edges,
+            vec![
+                ("logic", "walker@guard", "active".to_owned()),
+                (
+                    "walker@guard",
+                    "walker@2",
+                    "on_info {+esc_done} %+x%".to_owned()
+                ),
+                ("walker@guard", "remark@end", "on_actor_dist_le".to_owned()),
+                ("walker@2", "walker@guard", "on_timer".to_owned()),
+            ]
+        );
+        assert!(!graph.nodes[3].defined);
+    }
+
+    #[test]
+    fn reachability() {
+        let src = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+a} walker@2, {+a -a} walker@5, walker@1
+[walker@2]
+on_info = {+b} remark@3
+on_timer = 100 | remark@6
+[remark@3]
+on_info = {+b} walker@2
+[walker@4]
+on_info = walker@1
+[walker@5]
+[remark@6]
+";
+        let ltx = Ltx::from(src).unwrap();
+        let graph = Graph::from_ltx(&ltx).unwrap();
+        let x = graph.reachability();
+        let names = |x: &[usize]| {
+            x.iter()
+                .map(|x| graph.nodes[*x].name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&x.reachable),
+            vec!["walker@1", "walker@2", "remark@3", "remark@6"]
+        );
+        assert_eq!(names(&x.dead), vec!["walker@4", "walker@5"]);
+        assert_eq!(x.loops.len(), 1);
+        assert_eq!(names(&x.loops[0]), vec!["walker@2", "remark@3"]);
+        assert_eq!(names(&x.no_exit), vec!["remark@6"]);
+    }
+
+    #[test]
+    fn links_and_handlers() {
+        let src = "\
+[logic]
+active = walker@1
+on_hit = hit
+[walker@1]
+meet = meet@1
+on_info = {+a} walker@2
+[walker@2]
+on_info = {-a} walker@1, {+b} walker@3
+[walker@3]
+on_info = {+b} walker@2
+[meet@1]
+[hit]
+[walker@4]
+";
+        let ltx = Ltx::from(src).unwrap();
+        let graph = Graph::from_ltx(&ltx).unwrap();
+        let x = graph.reachability();
+        let names = |x: &[usize]| {
+            x.iter()
+                .map(|x| graph.nodes[*x].name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&x.reachable),
+            vec!["walker@1", "walker@2", "walker@3", "meet@1", "hit"]
+        );
+        assert_eq!(names(&x.dead), vec!["walker@4"]);
+        assert!(x.no_exit.is_empty());
+        // No world takes both `{+a}` and `{-a}`, but `{+b}` twice is fine.
+        assert_eq!(x.loops.len(), 1);
+        assert_eq!(names(&x.loops[0]), vec!["walker@2", "walker@3"]);
+    }
+
+    #[test]
+    fn dot() {
+        let ltx = Ltx::from("[logic]\nactive = a\n[a]\non_info = {+x} b %+y%\n").unwrap();
+        assert_eq!(
+            Graph::from_ltx(&ltx).unwrap().to_dot(),
+            "\
+digraph {
+    \"logic\";
+    \"a\";
+    \"b\" [style=dashed];
+    \"logic\" -> \"a\" [label=\"active\"];
+    \"a\" -> \"b\" [label=\"on_info {+x} %+y%\"];
+}
+"
+        );
+    }
+
+    #[test]
+    fn mermaid() {
+        let ltx = Ltx::from("[logic]\nactive = a\n[a]\non_info = {+x} b %+y%\n").unwrap();
+        assert_eq!(
+            Graph::from_ltx(&ltx).unwrap().to_mermaid(),
+            "\
+flowchart LR
+    n0[\"logic\"]
+    n1[\"a\"]
+    n2(\"b\")
+    n0 -->|\"active\"| n1
+    n1 -->|\"on_info {+x} %+y%\"| n2
+"
+        );
+    }
+}
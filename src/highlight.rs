@@ -0,0 +1,53 @@
+//! HTML rendering of condlists, one `<span>` per token with the kind name as
+//! its class, see [`TokenKind::name`]. Styling is left to the page.
+
+use crate::parser::{TokenKind, tokenize};
+
+/// A `<code class="condlist">` element.
+pub fn html(src: &str) -> Result<String, String> {
+    let mut out = String::from("<code class=\"condlist\">");
+    for token in tokenize(src)? {
+        let text = escape(token.span.as_str(src));
+        if token.kind == TokenKind::Whitespace {
+            out.push_str(&text);
+        } else {
+            out.push_str(&format!(
+                "<span class=\"{}\">{}</span>",
+                token.kind.name(),
+                text
+            ));
+        }
+    }
+    out.push_str("</code>");
+    Ok(out)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans() {
+        assert_eq!(
+            html("{=f(a)} x<y>, nil").unwrap(),
+            "<code class=\"condlist\">\
+             <span class=\"condition-delimiter\">{</span>\
+             <span class=\"call\">=f</span>\
+             <span class=\"punctuation\">(</span>\
+             <span class=\"argument\">a</span>\
+             <span class=\"punctuation\">)</span>\
+             <span class=\"condition-delimiter\">}</span> \
+             <span class=\"output\">x&lt;y&gt;</span>\
+             <span class=\"separator\">,</span> \
+             <span class=\"output\">nil</span>\
+             </code>"
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Editor features over an LTX file, independent of the protocol: the
+//! `condlist-lsp` binary only translates them. Offsets are bytes into the file.
+
+use std::path::{Path, PathBuf};
+
+use crate::catalog::{self, Kind};
+use crate::lint::SPECIAL_OUTPUTS;
+use crate::ltx::Ltx;
+use crate::parser::{Ast, Block, Slice, Token, tokenize};
+use crate::rename::Edit;
+use crate::visit::{Context, Position, Visit};
+use crate::xref::InfoIndex;
+
+/// Characters ending a name while typing.
+const DELIMITERS: &[char] = &[
+    ' ', '\t', '{', '}', '%', ',', '=', '!', '~', '+', '(', ')', ':', '|',
+];
+
+/// Converts byte offsets to LSP positions and back: 0-based lines and
+/// columns in UTF-16 code units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(x, _)| x + 1))
+            .collect();
+        Self { starts }
+    }
+
+    pub fn position(&self, text: &str, offset: usize) -> (u32, u32) {
+        let line = self.starts.partition_point(|x| *x <= offset) - 1;
+        let column = text[self.starts[line]..offset].encode_utf16().count();
+        (line as u32, column as u32)
+    }
+
+    /// Positions past the end of a line or of the text are clamped.
+    pub fn offset(&self, text: &str, line: u32, column: u32) -> usize {
+        let Some(start) = self.starts.get(line as usize).copied() else {
+            return text.len();
+        };
+        let end = self
+            .starts
+            .get(line as usize + 1)
+            .map_or(text.len(), |x| x - 1);
+        let mut units = 0;
+        for (ix, c) in text[start..end].char_indices() {
+            if units >= column as usize {
+                return start + ix;
+            }
+            units += c.len_utf16();
+        }
+        end
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Info,
+    Function(Kind),
+    Section,
+}
+
+/// A name under the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub name: String,
+    pub span: Slice,
+}
+
+/// The info portion, function or section at `offset`, in condlists or section
+/// headers.
+pub fn symbol_at(ltx: &Ltx, offset: usize) -> Option<Symbol> {
+    for section in ltx.sections() {
+        for name in std::iter::once(section.name()).chain(section.parents()) {
+            if contains(name, offset) {
+                return Some(Symbol {
+                    kind: SymbolKind::Section,
+                    name: ltx.slice_as_str(name).to_owned(),
+                    span: *name,
+                });
+            }
+        }
+    }
+
+    let condlist = ltx.condlists().find(|x| contains(&x.value, offset))?;
+    let ast = ltx.parse(&condlist).ok()?;
+    let mut finder = Finder {
+        offset: offset - condlist.value.index(),
+        found: None,
+    };
+    finder.visit_ast(&ast);
+    let mut found = finder.found?;
+    found.span = found.span.shifted(condlist.value.index());
+    Some(found)
+}
+
+fn contains(slice: &Slice, offset: usize) -> bool {
+    slice.index() <= offset && offset <= slice.end()
+}
+
+struct Finder {
+    offset: usize,
+    found: Option<Symbol>,
+}
+
+impl Finder {
+    fn check(&mut self, ast: &Ast, slice: &Slice, kind: SymbolKind) {
+        if contains(slice, self.offset) {
+            self.found = Some(Symbol {
+                kind,
+                name: ast.slice_as_str(slice).to_owned(),
+                span: *slice,
+            });
+        }
+    }
+}
+
+impl Visit for Finder {
+    fn visit_block(&mut self, ast: &Ast, block: &Block, cx: Context) {
+        match block {
+            Block::InfoPortion { key, .. } => self.check(ast, key, SymbolKind::Info),
+            Block::Call { function, .. } => {
+                let kind = match cx.position {
+                    Position::Condition => Kind::Condition,
+                    Position::Effect => Kind::Effect,
+                };
+                self.check(ast, function, SymbolKind::Function(kind))
+            }
+            Block::Chance { .. } => {}
+        }
+    }
+
+    fn visit_output(&mut self, ast: &Ast, out: &Slice, _: usize) {
+        if !SPECIAL_OUTPUTS.contains(&ast.slice_as_str(out)) {
+            self.check(ast, out, SymbolKind::Section);
+        }
+    }
+}
+
+/// Markdown describing the symbol at `offset`, and its span.
+pub fn hover(ltx: &Ltx, index: &InfoIndex, offset: usize) -> Option<(Slice, String)> {
+    let symbol = symbol_at(ltx, offset)?;
+    let text = match symbol.kind {
+        SymbolKind::Function(kind) => match catalog::lookup(kind, &symbol.name) {
+            Some(x) => format!("```\n{}\n```\n{}", x.signature(), x.doc),
+            None => format!("`{}`: not a known function", symbol.name),
+        },
+        SymbolKind::Info => format!(
+            "Info portion `{}`: given in {} places, checked in {}",
+            symbol.name,
+            index.givers(&symbol.name).count(),
+            index.readers(&symbol.name).count()
+        ),
+        SymbolKind::Section => match ltx.section(&symbol.name) {
+            Some(section) => {
+                let end = section
+                    .entries()
+                    .last()
+                    .map_or(section.name().end(), |x| x.value().unwrap_or(x.key()).end());
+                let start = section.name().index() - 1;
+                let src = ltx.slice_as_str(&Slice::new(start, end - start));
+                format!("```ini\n{}\n```", src)
+            }
+            None => format!("Section `{}` is not defined in this file", symbol.name),
+        },
+    };
+    Some((symbol.span, text))
+}
+
+/// Where the symbol at `offset` is defined: sections in this file, and
+/// wherever an info portion is given.
+pub fn definitions(
+    ltx: &Ltx,
+    file: &Path,
+    index: &InfoIndex,
+    offset: usize,
+) -> Vec<(PathBuf, Slice)> {
+    let Some(symbol) = symbol_at(ltx, offset) else {
+        return Vec::new();
+    };
+    match symbol.kind {
+        SymbolKind::Section => ltx
+            .section(&symbol.name)
+            .map(|x| (file.to_owned(), *x.name()))
+            .into_iter()
+            .collect(),
+        SymbolKind::Info => index
+            .givers(&symbol.name)
+            .map(|x| (x.file.clone(), x.span))
+            .collect(),
+        SymbolKind::Function(_) => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub kind: SymbolKind,
+    pub label: String,
+    /// The signature of functions.
+    pub detail: Option<String>,
+}
+
+/// Names that fit at `offset` while typing, and the span of the partial name
+/// they replace. Works on text that does not parse yet.
+pub fn completions(
+    ltx: &Ltx,
+    src: &str,
+    index: &InfoIndex,
+    offset: usize,
+) -> (Slice, Vec<Completion>) {
+    let line_start = src[..offset].rfind('\n').map_or(0, |x| x + 1);
+    let Some(eq) = src[line_start..offset].find('=') else {
+        return (Slice::new(offset, 0), Vec::new());
+    };
+    let mut value_start = line_start + eq + 1;
+    if let Some(bar) = src[value_start..offset].rfind('|') {
+        value_start += bar + 1;
+    }
+    let value = &src[value_start..offset];
+
+    let mut start = value.rfind(DELIMITERS).map_or(0, |x| x + 1);
+    let mut operator = value[..start].chars().next_back();
+    if value[start..].starts_with('-') {
+        start += 1;
+        operator = Some('-');
+    }
+    let span = Slice::new(value_start + start, value.len() - start);
+
+    let in_effects = value.matches('%').count() % 2 == 1;
+    let in_condition = value
+        .rfind('{')
+        .is_some_and(|x| value.rfind('}').is_none_or(|y| y < x));
+    let completions = match operator {
+        Some('=' | '!') => {
+            let kind = if in_effects {
+                Kind::Effect
+            } else {
+                Kind::Condition
+            };
+            catalog::functions()
+                .iter()
+                .filter(|x| x.kind == kind)
+                .map(|x| Completion {
+                    kind: SymbolKind::Function(kind),
+                    label: x.name.to_owned(),
+                    detail: Some(x.signature()),
+                })
+                .collect()
+        }
+        Some('+' | '-') => index
+            .keys()
+            .map(|x| Completion {
+                kind: SymbolKind::Info,
+                label: x.to_owned(),
+                detail: None,
+            })
+            .collect(),
+        _ if !in_effects && !in_condition => ltx
+            .sections()
+            .iter()
+            .map(|x| Completion {
+                kind: SymbolKind::Section,
+                label: ltx.slice_as_str(x.name()).to_owned(),
+                detail: None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    (span, completions)
+}
+
+/// Tokens of every condlist that parses, with file positions.
+pub fn semantic_tokens(ltx: &Ltx) -> Vec<Token> {
+    let mut out = Vec::new();
+    for condlist in ltx.condlists() {
+        let Ok(tokens) = tokenize(ltx.slice_as_str(&condlist.value)) else {
+            continue;
+        };
+        out.extend(tokens.into_iter().map(|x| Token {
+            kind: x.kind,
+            span: x.span.shifted(condlist.value.index()),
+        }));
+    }
+    out
+}
+
+/// Edits rewriting transitions, and any other value with condition or effect
+/// blocks, in canonical form. Values that do not parse are left alone.
+pub fn format_edits(ltx: &Ltx) -> Vec<Edit> {
+    let transitions = ltx.transitions().map(|x| x.value).collect::<Vec<_>>();
+    let mut out = Vec::new();
+    for condlist in ltx.condlists() {
+        let text = ltx.slice_as_str(&condlist.value);
+        if !transitions.contains(&condlist.value) && !text.contains(['{', '%']) {
+            continue;
+        }
+        let Ok(ast) = ltx.parse(&condlist) else {
+            continue;
+        };
+        let formatted = ast.to_string();
+        if formatted != text {
+            out.push(Edit {
+                span: condlist.value,
+                text: formatted,
+            });
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::TokenKind;
+
+    const LOGIC: &str = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+esc_done =is_alive(wolf)} walker@2 %=give_task(t) +found%
+on_actor_dist_le = 5 | {~30}walker@2
+
+[walker@2]
+path_walk = walk
+";
+
+    fn offset(needle: &str) -> usize {
+        LOGIC.find(needle).unwrap() + 1
+    }
+
+    #[test]
+    fn line_index() {
+        let text = "ab\nпривет x\n";
+        let index = LineIndex::new(text);
+        let x = text.find('x').unwrap();
+        assert_eq!(index.position(text, x), (1, 7));
+        assert_eq!(index.offset(text, 1, 7), x);
+        assert_eq!(index.offset(text, 0, 50), 2);
+        assert_eq!(index.offset(text, 9, 0), text.len());
+    }
+
+    #[test]
+    fn symbols() {
+        let ltx = Ltx::from(LOGIC).unwrap();
+        let symbol = |needle| {
+            let x = symbol_at(&ltx, offset(needle)).unwrap();
+            (x.kind, x.name, ltx.slice_as_str(&x.span))
+        };
+        assert_eq!(
+            symbol("is_alive"),
+            (
+                SymbolKind::Function(Kind::Condition),
+                "is_alive".to_owned(),
+                "is_alive"
+            )
+        );
+        assert_eq!(symbol("give_task").0, SymbolKind::Function(Kind::Effect));
+        assert_eq!(symbol("esc_done").0, SymbolKind::Info);
+        assert_eq!(symbol("walker@2 %").0, SymbolKind::Section);
+        assert_eq!(symbol("walker@1]").0, SymbolKind::Section);
+        assert_eq!(symbol_at(&ltx, offset("~30")), None);
+    }
+
+    #[test]
+    fn hover_and_definition() {
+        let ltx = Ltx::from(LOGIC).unwrap();
+        let mut index = InfoIndex::default();
+        index.add_ltx(Path::new("a.ltx"), &ltx);
+
+        let (span, text) = hover(&ltx, &index, offset("is_alive")).unwrap();
+        assert_eq!(ltx.slice_as_str(&span), "is_alive");
+        assert!(text.starts_with("```\n=is_alive(story_id)\n```\n"));
+        let (_, text) = hover(&ltx, &index, offset("walker@2 %")).unwrap();
+        assert_eq!(text, "```ini\n[walker@2]\npath_walk = walk\n```");
+
+        let found = definitions(&ltx, Path::new("a.ltx"), &index, offset("walker@2 %"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(ltx.slice_as_str(&found[0].1), "walker@2");
+        assert_eq!(found[0].1.index(), LOGIC.find("[walker@2]").unwrap() + 1);
+        let found = definitions(&ltx, Path::new("a.ltx"), &index, offset("esc_done"));
+        assert_eq!(found, vec![]);
+        let found = definitions(&ltx, Path::new("a.ltx"), &index, offset("found%"));
+        assert_eq!(ltx.slice_as_str(&found[0].1), "found");
+    }
+
+    #[test]
+    fn completion() {
+        let src = "[a]\non_info = {+x !is_a} %=sp\n[b]\n";
+        let ltx = Ltx::from(src).unwrap();
+        let mut index = InfoIndex::default();
+        index.add_ltx(Path::new("a.ltx"), &ltx);
+        let at = |needle: &str| {
+            let (span, items) =
+                completions(&ltx, src, &index, src.find(needle).unwrap() + needle.len());
+            let labels = items.iter().map(|x| x.label.clone()).collect::<Vec<_>>();
+            (span.as_str(src).to_owned(), labels)
+        };
+
+        let (span, labels) = at("!is_a");
+        assert_eq!(span, "is_a");
+        assert!(labels.contains(&"is_alive".to_owned()));
+        assert!(!labels.contains(&"give_task".to_owned()));
+        let (span, labels) = at("%=sp");
+        assert_eq!(span, "sp");
+        assert!(labels.contains(&"spawn_object".to_owned()));
+        assert_eq!(at("{+"), (String::new(), vec!["x".to_owned()]));
+        assert_eq!(
+            at("on_info = "),
+            (String::new(), vec!["a".to_owned(), "b".to_owned()])
+        );
+        assert_eq!(at("{+x "), (String::new(), vec![]));
+    }
+
+    #[test]
+    fn semantic() {
+        let src = "[a]\non_info = 5 | {+x} b\n";
+        let ltx = Ltx::from(src).unwrap();
+        let tokens = semantic_tokens(&ltx)
+            .into_iter()
+            .map(|x| (x.kind, x.span.as_str(src)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenKind::ConditionDelimiter, "{"),
+                (TokenKind::InfoSet, "+x"),
+                (TokenKind::ConditionDelimiter, "}"),
+                (TokenKind::Whitespace, " "),
+                (TokenKind::Output, "b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn formatting() {
+        let src = "[a]\non_info = {+x  =f(1)}b%+y%\nactive=c\npath = p,q\non_timer = 5 | {-x}c\n";
+        let ltx = Ltx::from(src).unwrap();
+        let edits = format_edits(&ltx);
+        assert_eq!(
+            crate::rename::apply(src, &edits),
+            "[a]\non_info = {+x =f(1)} b %+y%\nactive=c\npath = p,q\non_timer = 5 | {-x} c\n"
+        );
+    }
+}
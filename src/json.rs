@@ -0,0 +1,123 @@
+//! JSON output without dependencies. Every `Slice` is written together with the
+//! text it points at:
+//!
+//! ```json
+//! {"statements": [{
+//!     "condition": [{"kind": "info", "key": {"index": 2, "len": 1, "text": "a"}, "inverted": false}],
+//!     "output": {"index": 5, "len": 1, "text": "X"},
+//!     "effects": null
+//! }]}
+//! ```
+//!
+//! With the `serde` feature, `Ast` implements `Serialize` with the same schema.
+
+use crate::parser::{Ast, Block, Slice};
+
+/// Quotes and escapes `text` as a JSON string.
+pub fn string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            x if (x as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", x as u32)),
+            x => out.push(x),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn slice(ast: &Ast, slice: &Slice) -> String {
+    format!(
+        "{{\"index\":{},\"len\":{},\"text\":{}}}",
+        slice.index(),
+        slice.len(),
+        string(ast.slice_as_str(slice))
+    )
+}
+
+fn block(ast: &Ast, block: &Block) -> String {
+    match block {
+        Block::InfoPortion { key, inverted } => format!(
+            "{{\"kind\":\"info\",\"key\":{},\"inverted\":{}}}",
+            slice(ast, key),
+            inverted
+        ),
+        Block::Call {
+            function,
+            args,
+            inverted,
+        } => format!(
+            "{{\"kind\":\"call\",\"function\":{},\"args\":[{}],\"inverted\":{}}}",
+            slice(ast, function),
+            args.iter()
+                .map(|x| slice(ast, x))
+                .collect::<Vec<_>>()
+                .join(","),
+            inverted
+        ),
+        Block::Chance { val } => format!("{{\"kind\":\"chance\",\"value\":{}}}", slice(ast, val)),
+    }
+}
+
+fn blocks(ast: &Ast, blocks: Option<&[Block]>) -> String {
+    match blocks {
+        Some(x) => format!(
+            "[{}]",
+            x.iter()
+                .map(|b| block(ast, b))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        None => "null".to_owned(),
+    }
+}
+
+pub fn ast(ast: &Ast) -> String {
+    let statements = ast
+        .statements()
+        .iter()
+        .map(|x| {
+            format!(
+                "{{\"condition\":{},\"output\":{},\"effects\":{}}}",
+                blocks(ast, x.conditions().map(|c| c.blocks())),
+                x.val()
+                    .map(|v| slice(ast, v))
+                    .unwrap_or_else(|| "null".to_owned()),
+                blocks(ast, x.effects().map(|e| e.blocks())),
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("{{\"statements\":[{}]}}", statements.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape() {
+        assert_eq!(string("a\"b\\c\n\u{1}"), "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+
+    #[test]
+    fn statement() {
+        let ast = Ast::from("{+a =f(x)} X %~5%, Y").unwrap();
+        assert_eq!(
+            super::ast(&ast),
+            "{\"statements\":[\
+            {\"condition\":[\
+            {\"kind\":\"info\",\"key\":{\"index\":2,\"len\":1,\"text\":\"a\"},\"inverted\":false},\
+            {\"kind\":\"call\",\"function\":{\"index\":5,\"len\":1,\"text\":\"f\"},\
+            \"args\":[{\"index\":7,\"len\":1,\"text\":\"x\"}],\"inverted\":false}],\
+            \"output\":{\"index\":11,\"len\":1,\"text\":\"X\"},\
+            \"effects\":[{\"kind\":\"chance\",\"value\":{\"index\":15,\"len\":1,\"text\":\"5\"}}]},\
+            {\"condition\":null,\"output\":{\"index\":19,\"len\":1,\"text\":\"Y\"},\"effects\":null}]}"
+        );
+    }
+}
@@ -1,2 +1,30 @@
-mod parser;
-mod rebuild;
+pub mod analysis;
+pub mod builder;
+pub mod catalog;
+pub mod coverage;
+pub mod document;
+pub mod equivalence;
+pub mod eval;
+mod format;
+pub mod graph;
+pub mod highlight;
+pub mod ide;
+pub mod json;
+pub mod lint;
+pub mod logic;
+pub mod ltx;
+pub mod order;
+pub mod parser;
+pub mod rebuild;
+pub mod rename;
+pub mod repl;
+pub mod scenario;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod sim;
+pub mod simplify;
+pub mod table;
+pub mod trace;
+pub mod tree;
+pub mod visit;
+pub mod xref;
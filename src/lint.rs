@@ -0,0 +1,329 @@
+use std::borrow::Cow;
+
+use crate::analysis::{check_conditions, check_reachability, check_side_effects};
+use crate::graph::Graph;
+use crate::ltx::{Condlist, Ltx};
+use crate::parser::{Ast, Slice};
+
+/// Outputs that are not section names.
+pub(crate) const SPECIAL_OUTPUTS: &[&str] = &["nil", "true", "false", "never", "always"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Position in the linted file.
+    pub span: Slice,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub(crate) fn error(span: Slice, code: &'static str, message: String) -> Self {
+        Self {
+            span,
+            severity: Severity::Error,
+            code,
+            message,
+        }
+    }
+
+    pub(crate) fn warning(span: Slice, code: &'static str, message: String) -> Self {
+        Self {
+            span,
+            severity: Severity::Warning,
+            code,
+            message,
+        }
+    }
+}
+
+/// Parses an LTX file and lints it, reporting syntax errors at the start of the
+/// file.
+pub fn lint_source(src: &str) -> Vec<Diagnostic> {
+    match Ltx::from(src) {
+        Ok(ltx) => lint_ltx(&ltx),
+        Err(e) => vec![Diagnostic::error(Slice::new(0, 0), "ltx-error", e)],
+    }
+}
+
+/// Runs every check over the transitions of a logic file.
+pub fn lint_ltx(ltx: &Ltx) -> Vec<Diagnostic> {
+    lint_with(ltx, |x| ltx.parse(x).map(Cow::Owned))
+}
+
+/// [`lint_ltx`] with condlists parsed by `parse`, which may return cached
+/// trees.
+pub(crate) fn lint_with<'a>(
+    ltx: &Ltx<'a>,
+    parse: impl Fn(&Condlist) -> Result<Cow<'a, Ast<'a>>, String>,
+) -> Vec<Diagnostic> {
+    let mut out = Vec::new();
+    check_outputs(ltx, &parse, &mut out);
+    check_semantics(ltx, &parse, &mut out);
+    check_scheme(ltx, &parse, &mut out);
+    out
+}
+
+/// Reports logic sections never switched to, loops that never end and
+/// sections that are never left, see [`Graph::reachability`]. Files without
+/// `active` are not logic schemes.
+fn check_scheme<'a>(
+    ltx: &Ltx<'a>,
+    parse: &impl Fn(&Condlist) -> Result<Cow<'a, Ast<'a>>, String>,
+    out: &mut Vec<Diagnostic>,
+) {
+    let Ok(graph) = Graph::from_ltx_with(ltx, parse) else {
+        return;
+    };
+    if !graph.edges.iter().any(|x| x.key == "active") {
+        return;
+    }
+    let report = graph.reachability();
+    let span = |node: usize| ltx.section(&graph.nodes[node].name).map(|x| *x.name());
+
+    for node in report.dead {
+        out.extend(span(node).map(|x| {
+            Diagnostic::warning(
+                x,
+                "dead-section",
+                format!(
+                    "Section `{}` is never switched to from `active`",
+                    graph.nodes[node].name
+                ),
+            )
+        }));
+    }
+    for node in report.no_exit {
+        out.extend(span(node).map(|x| {
+            Diagnostic::warning(
+                x,
+                "no-exit",
+                format!(
+                    "Section `{}` never switches to another section",
+                    graph.nodes[node].name
+                ),
+            )
+        }));
+    }
+    for nodes in report.loops {
+        let names = nodes
+            .iter()
+            .map(|x| format!("`{}`", graph.nodes[*x].name))
+            .collect::<Vec<_>>();
+        out.extend(span(nodes[0]).map(|x| {
+            Diagnostic::warning(
+                x,
+                "endless-loop",
+                format!(
+                    "Sections {} switch between each other forever, the transitions have no effects",
+                    names.join(", ")
+                ),
+            )
+        }));
+    }
+}
+
+/// Runs the checks of [`crate::analysis`] over every condlist of the file.
+fn check_semantics<'a>(
+    ltx: &Ltx<'a>,
+    parse: &impl Fn(&Condlist) -> Result<Cow<'a, Ast<'a>>, String>,
+    out: &mut Vec<Diagnostic>,
+) {
+    for condlist in ltx.condlists() {
+        let Ok(ast) = parse(&condlist) else {
+            continue;
+        };
+        let found = check_conditions(&ast)
+            .into_iter()
+            .chain(check_reachability(&ast))
+            .chain(check_side_effects(&ast));
+        out.extend(found.map(|mut x| {
+            x.span = x.span.shifted(condlist.value.index());
+            x
+        }));
+    }
+}
+
+/// Reports condlists that fail to parse and outputs pointing to sections
+/// missing from the file.
+fn check_outputs<'a>(
+    ltx: &Ltx<'a>,
+    parse: &impl Fn(&Condlist) -> Result<Cow<'a, Ast<'a>>, String>,
+    out: &mut Vec<Diagnostic>,
+) {
+    for condlist in ltx.transitions() {
+        let ast = match parse(&condlist) {
+            Ok(x) => x,
+            Err(e) => {
+                out.push(Diagnostic::error(condlist.value, "parse-error", e));
+                continue;
+            }
+        };
+
+        for statement in ast.statements() {
+            let Some(val) = statement.val() else {
+                continue;
+            };
+            let name = ast.slice_as_str(val);
+            if SPECIAL_OUTPUTS.contains(&name) || ltx.section(name).is_some() {
+                continue;
+            }
+            out.push(Diagnostic::error(
+                val.shifted(condlist.value.index()),
+                "dangling-output",
+                format!("Section `{}` is not defined in this file", name),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_outputs() {
+        let src = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+a} walker@2, nil
+[walker@2]
+on_info = {=f} true, never
+";
+        let ltx = Ltx::from(src).unwrap();
+        assert_eq!(lint_ltx(&ltx), vec![]);
+    }
+
+    #[test]
+    fn dangling_output() {
+        let src = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+a} walker@2 %+b%, walker@1
+";
+        let ltx = Ltx::from(src).unwrap();
+        let diagnostics = lint_ltx(&ltx);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "dangling-output");
+        assert_eq!(ltx.slice_as_str(&diagnostics[0].span), "walker@2");
+    }
+
+    #[test]
+    fn semantic_checks() {
+        let src = "[walker@1]\non_info = {+a -a} walker@1\ncombat_ignore_cond = {+b +b} true\n";
+        let ltx = Ltx::from(src).unwrap();
+        let diagnostics = lint_ltx(&ltx);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|x| (x.code, ltx.slice_as_str(&x.span)))
+                .collect::<Vec<_>>(),
+            vec![("unsatisfiable-condition", "-a"), ("duplicate-block", "+b"),]
+        );
+    }
+
+    #[test]
+    fn non_ascii() {
+        let src = "\
+[logic]
+active = walker@1
+[walker@1]
+on_info = {=f(привет) +a +a} walker@2, {+b} привет
+[walker@2]
+";
+        let ltx = Ltx::from(src).unwrap();
+        let diagnostics = lint_ltx(&ltx);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|x| (x.code, ltx.slice_as_str(&x.span)))
+                .collect::<Vec<_>>(),
+            vec![
+                ("dangling-output", "привет"),
+                ("duplicate-block", "+a"),
+                ("no-exit", "walker@2")
+            ]
+        );
+    }
+
+    #[test]
+    fn scheme_checks() {
+        let src = "\
+[logic]
+active = walker@1
+[walker@1]
+on_info = {+a} remark@1
+[remark@1]
+on_info = walker@1
+[remark@2]
+";
+        let ltx = Ltx::from(src).unwrap();
+        let diagnostics = lint_ltx(&ltx);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|x| (x.code, ltx.slice_as_str(&x.span)))
+                .collect::<Vec<_>>(),
+            vec![("dead-section", "remark@2"), ("endless-loop", "walker@1")]
+        );
+    }
+
+    #[test]
+    fn scheme_sections() {
+        let src = "\
+[logic]
+active = walker@guard
+on_hit = hit
+on_death = death
+[walker@guard]
+path_walk = guard_walk
+meet = meet@guard
+wounded = wounded@guard
+on_info = {+a} walker@2
+[walker@2]
+on_info = {-a} walker@guard
+[meet@guard]
+close_anim = nil
+[wounded@guard]
+[hit]
+[death]
+[walker@3]
+on_info = walker@guard
+";
+        let ltx = Ltx::from(src).unwrap();
+        let diagnostics = lint_ltx(&ltx);
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|x| (x.code, ltx.slice_as_str(&x.span)))
+                .collect::<Vec<_>>(),
+            vec![("dead-section", "walker@3")]
+        );
+
+        let src = "[logic]\nactive = walker@1\n[walker@1]\non_info = {+a} walker@2\n[walker@2]\n";
+        let ltx = Ltx::from(src).unwrap();
+        let diagnostics = lint_ltx(&ltx);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "no-exit");
+        assert_eq!(ltx.slice_as_str(&diagnostics[0].span), "walker@2");
+    }
+
+    #[test]
+    fn parse_error() {
+        let src = "[logic]\nactive = {{+a}} walker@1\n[walker@1]\n";
+        let ltx = Ltx::from(src).unwrap();
+        let diagnostics = lint_ltx(&ltx);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "parse-error");
+    }
+}
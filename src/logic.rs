@@ -0,0 +1,244 @@
+//! Conditions as boolean formulas. Info portions and calls are opaque atoms,
+//! and since one roll is shared by every `~N` of an evaluation (see
+//! [`crate::eval::evaluate`]), the chances of a condition reduce to the lowest
+//! one.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::parser::{Ast, Block, Statement};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Atom {
+    Info(String),
+    Call {
+        function: String,
+        args: Vec<String>,
+    },
+    /// A `~` check whose value is not a number. The evaluator fails on it,
+    /// so whether it passes is as unknown as a call.
+    Chance(String),
+}
+
+impl fmt::Display for Atom {
+    /// The atom as a block checking it is true, `+x` or `=f(a:b)`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Atom::Info(key) => write!(f, "+{}", key),
+            Atom::Call { function, args } if args.is_empty() => write!(f, "={}", function),
+            Atom::Call { function, args } => write!(f, "={}({})", function, args.join(":")),
+            Atom::Chance(val) => write!(f, "~{}", val),
+        }
+    }
+}
+
+/// An atom together with the value a block requires from it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Literal {
+    pub atom: Atom,
+    pub value: bool,
+}
+
+impl fmt::Display for Literal {
+    /// The literal as a condition block, `-x` or `!f(a:b)` when negated.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let atom = self.atom.to_string();
+        if self.value {
+            return f.write_str(&atom);
+        }
+        let prefix = match self.atom {
+            Atom::Info(_) => '-',
+            Atom::Call { .. } => '!',
+            // Not a block, only shown in tables.
+            Atom::Chance(_) => return write!(f, "!{}", atom),
+        };
+        write!(f, "{}{}", prefix, &atom[1..])
+    }
+}
+
+impl Literal {
+    /// `None` for chances with a number, see [`chance`].
+    pub fn from_block(ast: &Ast, block: &Block) -> Option<Self> {
+        match block {
+            Block::InfoPortion { key, inverted } => Some(Self {
+                atom: Atom::Info(ast.slice_as_str(key).to_owned()),
+                value: !inverted,
+            }),
+            Block::Call {
+                function,
+                args,
+                inverted,
+            } => Some(Self {
+                atom: Atom::Call {
+                    function: ast.slice_as_str(function).to_owned(),
+                    args: args
+                        .iter()
+                        .map(|x| ast.slice_as_str(x).to_owned())
+                        .collect(),
+                },
+                value: !inverted,
+            }),
+            Block::Chance { val } => chance(ast, block).is_none().then(|| Self {
+                atom: Atom::Chance(ast.slice_as_str(val).to_owned()),
+                value: true,
+            }),
+        }
+    }
+}
+
+/// Percentage of a `~N` block, saturated at 100. `None` for other blocks and
+/// for values that are not a number, which the evaluator rejects.
+pub fn chance(ast: &Ast, block: &Block) -> Option<u32> {
+    match block {
+        Block::Chance { val } => Some(ast.slice_as_str(val).parse::<u32>().ok()?.min(100)),
+        _ => None,
+    }
+}
+
+/// The condition of a statement: every literal must hold and the roll must
+/// not exceed `chance`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Conjunction {
+    pub literals: BTreeMap<Atom, bool>,
+    /// `None` when there is no chance or it is at least 100.
+    pub chance: Option<u32>,
+    /// Requires an atom to be both true and false, or has `~0`.
+    pub contradictory: bool,
+}
+
+impl Conjunction {
+    pub fn from_statement(ast: &Ast, statement: &Statement) -> Self {
+        let blocks = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+        Self::from_blocks(ast, blocks)
+    }
+
+    /// The condition of some condition blocks.
+    pub fn from_blocks(ast: &Ast, blocks: &[Block]) -> Self {
+        let mut out = Self::default();
+        for block in blocks {
+            if let Some(x) = chance(ast, block) {
+                let lowest = out.chance.map_or(x, |c| c.min(x));
+                out.chance = (lowest < 100).then_some(lowest);
+                out.contradictory |= lowest == 0;
+            } else if let Some(x) = Literal::from_block(ast, block) {
+                match out.literals.get(&x.atom) {
+                    Some(value) if *value != x.value => out.contradictory = true,
+                    _ => {
+                        out.literals.insert(x.atom, x.value);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Holds exactly when both `self` and `other` hold.
+    pub fn and(&self, other: &Self) -> Self {
+        let mut out = self.clone();
+        for (atom, value) in &other.literals {
+            match out.literals.get(atom) {
+                Some(x) if x != value => out.contradictory = true,
+                _ => {
+                    out.literals.insert(atom.clone(), *value);
+                }
+            }
+        }
+        out.chance = match (self.chance, other.chance) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        out.contradictory |= other.contradictory;
+        out
+    }
+
+    /// Holds whatever the world looks like.
+    pub fn is_always(&self) -> bool {
+        !self.contradictory && self.literals.is_empty() && self.chance.is_none()
+    }
+
+    /// Whenever `self` holds, `other` holds too.
+    pub fn implies(&self, other: &Self) -> bool {
+        if self.contradictory {
+            return true;
+        }
+        if other.contradictory {
+            return false;
+        }
+        let literals = other
+            .literals
+            .iter()
+            .all(|(atom, value)| self.literals.get(atom) == Some(value));
+        let chance = match (self.chance, other.chance) {
+            (_, None) => true,
+            (Some(a), Some(b)) => a <= b,
+            (None, Some(_)) => false,
+        };
+        literals && chance
+    }
+
+    /// Whether the condition holds for an assignment of atoms and a roll in
+    /// `1..=100`. Atoms missing from `world` are false.
+    pub fn holds(&self, world: &BTreeMap<Atom, bool>, roll: u32) -> bool {
+        !self.contradictory
+            && self
+                .literals
+                .iter()
+                .all(|(atom, value)| world.get(atom).copied().unwrap_or(false) == *value)
+            && self.chance.is_none_or(|x| roll <= x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conjunction(src: &str) -> Conjunction {
+        let ast = Ast::from(src).unwrap();
+        Conjunction::from_statement(&ast, &ast.statements()[0])
+    }
+
+    #[test]
+    fn contradictory() {
+        assert!(conjunction("{+a -a} X").contradictory);
+        assert!(conjunction("{!f =f} X").contradictory);
+        assert!(conjunction("{~0} X").contradictory);
+        assert!(!conjunction("{=f(a) !f(b)} X").contradictory);
+    }
+
+    #[test]
+    fn invalid_chance() {
+        // Unknown like a call, neither always nor never passing.
+        let x = conjunction("{~} X");
+        assert!(!x.is_always() && !x.contradictory);
+        assert_eq!(x.chance, None);
+        assert_eq!(x.literals.get(&Atom::Chance(String::new())), Some(&true));
+        assert!(!conjunction("{+a} X").implies(&conjunction("{~ +a} X")));
+    }
+
+    #[test]
+    fn implies() {
+        assert!(conjunction("{+a +b} X").implies(&conjunction("{+a} X")));
+        assert!(!conjunction("{+a} X").implies(&conjunction("{+a +b} X")));
+        assert!(conjunction("{+a ~10} X").implies(&conjunction("{~30} X")));
+        assert!(!conjunction("{+a ~30} X").implies(&conjunction("{~10} X")));
+        assert!(conjunction("{+a} X").implies(&conjunction("X")));
+        assert!(conjunction("{~100} X").is_always());
+    }
+
+    #[test]
+    fn holds() {
+        let x = conjunction("{+a !f(1) ~40} X");
+        let mut world = BTreeMap::new();
+        world.insert(Atom::Info("a".to_owned()), true);
+        assert!(x.holds(&world, 40));
+        assert!(!x.holds(&world, 41));
+        world.insert(
+            Atom::Call {
+                function: "f".to_owned(),
+                args: vec!["1".to_owned()],
+            },
+            true,
+        );
+        assert!(!x.holds(&world, 1));
+    }
+}
@@ -0,0 +1,269 @@
+use crate::parser::{Ast, Slice};
+
+/// Keys whose values are condlists selecting the next logic section.
+const TRANSITION_KEYS: &[&str] = &["active"];
+
+/// Prefix of keys whose values are `[param |] condlist` transitions.
+const TRANSITION_PREFIX: &str = "on_";
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    key: Slice,
+    value: Option<Slice>,
+}
+
+impl Entry {
+    pub fn key(&self) -> &Slice {
+        &self.key
+    }
+
+    pub fn value(&self) -> Option<&Slice> {
+        self.value.as_ref()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Section {
+    name: Slice,
+    parents: Vec<Slice>,
+    entries: Vec<Entry>,
+}
+
+impl Section {
+    pub fn name(&self) -> &Slice {
+        &self.name
+    }
+
+    pub fn parents(&self) -> &[Slice] {
+        &self.parents
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+}
+
+/// A condlist found in an LTX entry. `value` points at the condlist part of the
+/// entry value, without a leading `param |`.
+#[derive(Debug, PartialEq)]
+pub struct Condlist<'s> {
+    pub section: &'s Section,
+    pub entry: &'s Entry,
+    pub value: Slice,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Ltx<'a> {
+    orig: &'a str,
+    sections: Vec<Section>,
+}
+
+impl<'a> Ltx<'a> {
+    pub fn from(src: &'a str) -> Result<Self, String> {
+        let mut sections: Vec<Section> = Vec::new();
+        let mut offset = 0;
+
+        for (line_no, line) in src.split_inclusive('\n').enumerate() {
+            let start = offset;
+            offset += line.len();
+
+            let content = match line.find(';') {
+                Some(x) => &line[..x],
+                None => line,
+            };
+            let Some(content) = trimmed(content, start) else {
+                continue;
+            };
+            let text = &src[content.index()..content.end()];
+
+            if text.starts_with('#') {
+                continue;
+            }
+
+            if text.starts_with('[') {
+                let Some(close) = text.find(']') else {
+                    return Err(format!("Unclosed section header on line {}", line_no + 1));
+                };
+                let name = trimmed(&text[1..close], content.index() + 1)
+                    .ok_or_else(|| format!("Empty section name on line {}", line_no + 1))?;
+                let rest = &text[close + 1..];
+                let parents = match rest.trim_start().strip_prefix(':') {
+                    Some(list) => {
+                        let mut parents = Vec::new();
+                        let mut ix = content.index() + text.len() - list.len();
+                        for parent in list.split(',') {
+                            parents.extend(trimmed(parent, ix));
+                            ix += parent.len() + 1;
+                        }
+                        parents
+                    }
+                    None if rest.trim().is_empty() => Vec::new(),
+                    None => {
+                        return Err(format!(
+                            "Unexpected text after section header on line {}",
+                            line_no + 1
+                        ));
+                    }
+                };
+                sections.push(Section {
+                    name,
+                    parents,
+                    entries: Vec::new(),
+                });
+                continue;
+            }
+
+            let Some(section) = sections.last_mut() else {
+                return Err(format!("Entry outside of section on line {}", line_no + 1));
+            };
+            let entry = match text.find('=') {
+                Some(eq) => Entry {
+                    key: trimmed(&text[..eq], content.index())
+                        .ok_or_else(|| format!("Entry without key on line {}", line_no + 1))?,
+                    value: trimmed(&text[eq + 1..], content.index() + eq + 1),
+                },
+                None => Entry {
+                    key: content,
+                    value: None,
+                },
+            };
+            section.entries.push(entry);
+        }
+
+        Ok(Self {
+            orig: src,
+            sections,
+        })
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections
+            .iter()
+            .find(|x| self.slice_as_str(&x.name) == name)
+    }
+
+    pub fn slice_as_str(&self, slice: &Slice) -> &'a str {
+        &self.orig[slice.index()..slice.end()]
+    }
+
+    /// Every entry with a value, split as `[param |] condlist`. Whether the
+    /// value really is a condlist is up to the caller, see [`Ltx::parse`].
+    pub fn condlists(&self) -> impl Iterator<Item = Condlist<'_>> {
+        self.sections.iter().flat_map(move |section| {
+            section.entries.iter().filter_map(move |entry| {
+                let value = entry.value?;
+                let text = self.slice_as_str(&value);
+                let value = match text.find('|') {
+                    Some(bar) => trimmed(&text[bar + 1..], value.index() + bar + 1)?,
+                    None => value,
+                };
+                Some(Condlist {
+                    section,
+                    entry,
+                    value,
+                })
+            })
+        })
+    }
+
+    /// The condlist of `key` in `section`.
+    pub fn condlist(&self, section: &str, key: &str) -> Option<Condlist<'_>> {
+        self.condlists().find(|x| {
+            self.slice_as_str(&x.section.name) == section && self.slice_as_str(&x.entry.key) == key
+        })
+    }
+
+    /// Entries whose values are condlists choosing the next logic section:
+    /// `active` and every `on_*` key.
+    pub fn transitions(&self) -> impl Iterator<Item = Condlist<'_>> {
+        self.condlists()
+            .filter(|x| is_transition(self.slice_as_str(&x.entry.key)))
+    }
+
+    /// Parses a condlist. Slices of the returned [`Ast`] are relative to
+    /// `condlist.value`, use [`Slice::shifted`] to get file positions.
+    pub fn parse(&self, condlist: &Condlist) -> Result<Ast<'a>, String> {
+        Ast::from(self.slice_as_str(&condlist.value))
+    }
+}
+
+/// Whether entries with this key are transitions, see [`Ltx::transitions`].
+pub(crate) fn is_transition(key: &str) -> bool {
+    TRANSITION_KEYS.contains(&key) || key.starts_with(TRANSITION_PREFIX)
+}
+
+fn trimmed(text: &str, ix: usize) -> Option<Slice> {
+    let start = text.len() - text.trim_start().len();
+    let len = text.trim().len();
+    if len == 0 {
+        return None;
+    }
+    Some(Slice::new(ix + start, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIC: &str = "\
+#include \"base.ltx\"
+[logic]
+active = walker@guard ; start here
+
+[walker@guard]:base, other
+path_walk = guard_walk
+on_info = {+esc_done} walker@2 %+x%
+on_actor_dist_le = 5 | remark@end
+";
+
+    #[test]
+    fn sections() {
+        let ltx = Ltx::from(LOGIC).unwrap();
+        assert_eq!(ltx.sections().len(), 2);
+        let guard = ltx.section("walker@guard").unwrap();
+        assert_eq!(
+            guard
+                .parents()
+                .iter()
+                .map(|x| ltx.slice_as_str(x))
+                .collect::<Vec<_>>(),
+            vec!["base", "other"]
+        );
+        assert_eq!(guard.entries().len(), 3);
+        let entry = &guard.entries()[0];
+        assert_eq!(ltx.slice_as_str(entry.key()), "path_walk");
+        assert_eq!(ltx.slice_as_str(entry.value().unwrap()), "guard_walk");
+    }
+
+    #[test]
+    fn comment_stripped() {
+        let ltx = Ltx::from(LOGIC).unwrap();
+        let logic = ltx.section("logic").unwrap();
+        assert_eq!(
+            ltx.slice_as_str(logic.entries()[0].value().unwrap()),
+            "walker@guard"
+        );
+    }
+
+    #[test]
+    fn transitions() {
+        let ltx = Ltx::from(LOGIC).unwrap();
+        let values = ltx
+            .transitions()
+            .map(|x| ltx.slice_as_str(&x.value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec!["walker@guard", "{+esc_done} walker@2 %+x%", "remark@end"]
+        );
+    }
+
+    #[test]
+    fn entry_outside_section() {
+        assert!(Ltx::from("a = b\n[s]").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! The order a condlist is evaluated in, written down once for
+//! [`crate::eval`] and the Lua of [`crate::rebuild`]. It is the one of
+//! `xr_logic.pick_section_from_condlist`:
+//!
+//! 1. Statements are tried in order, the first whose conditions all hold is
+//!    chosen and the others are not looked at.
+//! 2. Condition blocks are checked left to right, and checking stops at the
+//!    first one that fails: later blocks, calls included, do not run.
+//! 3. One number in `1..=100` is rolled for the whole evaluation and shared
+//!    by every `~N`, which passes when the roll is at most `N`.
+//! 4. The effects of the chosen statement run left to right, then its output
+//!    is returned.
+//!
+//! Since calls may not run, a condition with side effects behaves
+//! differently depending on the blocks before it, see
+//! [`crate::catalog::Function::side_effects`].
+
+use crate::catalog::{self, Kind};
+use crate::parser::{Ast, Block, Statement};
+
+/// One step of evaluating a statement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op<'s> {
+    /// Checks the condition block at this index. A failing check ends the
+    /// statement.
+    Check(usize, &'s Block),
+    /// Runs an effect block, once every check passed.
+    Run(&'s Block),
+    /// Chooses the statement and returns its output.
+    Return,
+}
+
+/// The steps of `statement`, in order.
+pub fn ops(statement: &Statement) -> impl Iterator<Item = Op<'_>> {
+    let conditions = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+    let effects = statement.effects().map(|x| x.blocks()).unwrap_or(&[]);
+    let checks = conditions.iter().enumerate().map(|(i, x)| Op::Check(i, x));
+    checks
+        .chain(effects.iter().map(Op::Run))
+        .chain(std::iter::once(Op::Return))
+}
+
+/// The condition blocks checked before block `ix` of `statement`: block `ix`
+/// only runs if they all pass.
+pub fn guards(statement: &Statement, ix: usize) -> &[Block] {
+    let conditions = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+    &conditions[..ix.min(conditions.len())]
+}
+
+/// Whether `block` calls a condition with side effects, per the catalog.
+/// Whether they happen depends on the blocks checked before it.
+pub fn impure(ast: &Ast, block: &Block) -> bool {
+    let Block::Call { function, .. } = block else {
+        return false;
+    };
+    catalog::lookup(Kind::Condition, ast.slice_as_str(function)).is_some_and(|x| x.side_effects)
+}
+
+/// Whether `~chance` passes with the shared roll.
+pub fn chance_passes(roll: u32, chance: u32) -> bool {
+    roll <= chance
+}
+
+/// Lua rolling the number shared by the `~N` of a condlist. Unlike
+/// `xr_logic`, which rolls at the first `~N` checked, it is rolled up front;
+/// which statement is chosen does not change.
+pub(crate) const LUA_ROLL: &str = "local roll = math.random(1, 100)\n";
+
+/// Lua for `~chance`, see [`chance_passes`].
+pub(crate) fn lua_chance(chance: &str) -> String {
+    format!("roll <= {}", chance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::{State, World, evaluate};
+    use crate::parser::Ast;
+    use crate::rebuild::to_lua;
+    use crate::trace::Recorder;
+
+    /// A world logging every condition call.
+    struct Log(State, Vec<String>);
+
+    impl World for Log {
+        fn has_info(&self, key: &str) -> bool {
+            self.0.has_info(key)
+        }
+        fn give_info(&mut self, key: &str) {
+            self.0.give_info(key)
+        }
+        fn disable_info(&mut self, key: &str) {
+            self.0.disable_info(key)
+        }
+        fn condition(&mut self, function: &str, args: &[&str]) -> Result<bool, String> {
+            self.1.push(function.to_owned());
+            self.0.condition(function, args)
+        }
+        fn effect(&mut self, function: &str, args: &[&str]) -> Result<(), String> {
+            self.0.effect(function, args)
+        }
+        fn roll(&mut self) -> u32 {
+            self.1.push("roll".to_owned());
+            self.0.roll()
+        }
+    }
+
+    #[test]
+    fn steps() {
+        let ast = Ast::from("{+a ~5} x %+b =f%").unwrap();
+        let ops = ops(&ast.statements()[0])
+            .map(|x| match x {
+                Op::Check(i, b) => format!("check {} {}", i, ast.format_block(b)),
+                Op::Run(b) => format!("run {}", ast.format_block(b)),
+                Op::Return => "return".to_owned(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ops,
+            vec!["check 0 +a", "check 1 ~5", "run +b", "run =f", "return"]
+        );
+        assert_eq!(guards(&ast.statements()[0], 1).len(), 1);
+    }
+
+    #[test]
+    fn evaluator_follows_the_order() {
+        let ast = Ast::from("{=a =b =c} x, {~100 =d ~100} y %=e%").unwrap();
+        let mut state = State::default();
+        state.stub("a", None, true);
+        state.stub("b", None, false);
+        state.stub("d", None, true);
+        let mut log = Log(state, Vec::new());
+        let mut recorder = Recorder::new(&mut log);
+        let outcome = evaluate(&ast, &mut recorder).unwrap();
+        assert_eq!(outcome.output.as_deref(), Some("y"));
+        assert_eq!(recorder.actions.len(), 1);
+        // `c` is never called, and the second `~100` reuses the roll.
+        assert_eq!(log.1, vec!["a", "b", "roll", "d"]);
+    }
+
+    #[test]
+    fn lua_follows_the_order() {
+        let lua = to_lua(&Ast::from("{=a ~30 +b !c} x %=e%, y").unwrap());
+        assert!(lua.starts_with(LUA_ROLL));
+        let positions = [
+            "xr_conditions.a(",
+            "roll <= 30",
+            "db.actor:has_info(\"b\")",
+            "not xr_conditions.c(",
+            "xr_effects.e(",
+            "return \"x\"",
+            "return \"y\"",
+        ]
+        .map(|x| lua.find(x).unwrap_or_else(|| panic!("{} in {}", x, lua)));
+        assert!(positions.is_sorted(), "{}", lua);
+        assert!(!to_lua(&Ast::from("{+a} x").unwrap()).contains("roll"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 #[derive(Debug)]
 struct Parser<'a> {
     ast: Ast<'a>,
@@ -5,6 +7,8 @@ struct Parser<'a> {
     current: Option<CondOrEffect>,
     current_block: Option<Block>,
     state: CallState,
+    /// Collected only by [`tokenize`].
+    tokens: Option<Vec<Token>>,
 }
 
 impl<'a> Parser<'a> {
@@ -15,11 +19,90 @@ impl<'a> Parser<'a> {
             current: Default::default(),
             current_block: Default::default(),
             state: Default::default(),
+            tokens: None,
+        }
+    }
+}
+
+/// What a range of a condlist is, for highlighting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// `+x`: gives the info portion in effects, checks it in conditions.
+    InfoSet,
+    /// `-x`: disables the info portion in effects, checks it is missing in
+    /// conditions.
+    InfoClear,
+    /// `=f`, the function of a call.
+    Call,
+    /// `!f`, the function of a call whose result is negated.
+    NegatedCall,
+    Argument,
+    /// `~N`.
+    Chance,
+    /// The section a statement chooses.
+    Output,
+    /// `,` between statements.
+    Separator,
+    /// `(`, `:` and `)` of a call.
+    Punctuation,
+    /// `{` and `}`.
+    ConditionDelimiter,
+    /// `%`.
+    EffectDelimiter,
+    Whitespace,
+}
+
+impl TokenKind {
+    /// Kebab-case name, e.g. `info-set`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::InfoSet => "info-set",
+            TokenKind::InfoClear => "info-clear",
+            TokenKind::Call => "call",
+            TokenKind::NegatedCall => "negated-call",
+            TokenKind::Argument => "argument",
+            TokenKind::Chance => "chance",
+            TokenKind::Output => "output",
+            TokenKind::Separator => "separator",
+            TokenKind::Punctuation => "punctuation",
+            TokenKind::ConditionDelimiter => "condition-delimiter",
+            TokenKind::EffectDelimiter => "effect-delimiter",
+            TokenKind::Whitespace => "whitespace",
         }
     }
+
+    /// Whether consecutive characters of this kind form one token.
+    fn joins(&self) -> bool {
+        !matches!(
+            self,
+            TokenKind::Separator
+                | TokenKind::Punctuation
+                | TokenKind::ConditionDelimiter
+                | TokenKind::EffectDelimiter
+        )
+    }
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Slice,
+}
+
+/// Splits a condlist into tokens covering every character, classified the
+/// way [`Ast::from`] reads them.
+pub fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut parser = Parser::new(src);
+    parser.tokens = Some(Vec::new());
+    for (i, char) in src.char_indices() {
+        parser.eat(&char, i)?;
+    }
+    let tokens = parser.tokens.take().unwrap_or_default();
+    parser.finish()?;
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Condition(Vec<Block>);
 
 impl Condition {
@@ -28,7 +111,7 @@ impl Condition {
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Effect(Vec<Block>);
 
 impl Effect {
@@ -54,6 +137,9 @@ impl CondOrEffect {
 
 impl<'a> Parser<'a> {
     fn eat(&mut self, ch: &char, ix: usize) -> Result<(), String> {
+        if self.tokens.is_some() {
+            self.push_token(self.classify(*ch), *ch, ix);
+        }
         match ch {
             '{' => {
                 if self.current.is_some() {
@@ -141,7 +227,7 @@ impl<'a> Parser<'a> {
                         .statement
                         .out
                         .get_or_insert_with(|| Slice::started_at(ix))
-                        .push_ch(),
+                        .push_ch(*ch),
                     Some(x) => x.push_ch(ch.to_owned(), &mut self.state)?,
                 };
             }
@@ -150,6 +236,53 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
+    /// The kind of token `ch` belongs to, before it is eaten.
+    fn classify(&self, ch: char) -> TokenKind {
+        match ch {
+            '{' | '}' => TokenKind::ConditionDelimiter,
+            ',' => TokenKind::Separator,
+            '\t' | ' ' => TokenKind::Whitespace,
+            '+' => TokenKind::InfoSet,
+            '-' => TokenKind::InfoClear,
+            '~' => TokenKind::Chance,
+            '=' => TokenKind::Call,
+            '!' => TokenKind::NegatedCall,
+            '%' => TokenKind::EffectDelimiter,
+            _ => match &self.current_block {
+                None => TokenKind::Output,
+                Some(Block::InfoPortion {
+                    inverted: false, ..
+                }) => TokenKind::InfoSet,
+                Some(Block::InfoPortion { inverted: true, .. }) => TokenKind::InfoClear,
+                Some(Block::Chance { .. }) => TokenKind::Chance,
+                Some(Block::Call { inverted, .. }) => match (&self.state, ch) {
+                    (CallState::None, '(') | (CallState::Opened(_), ':' | ')') => {
+                        TokenKind::Punctuation
+                    }
+                    (CallState::None, _) if *inverted => TokenKind::NegatedCall,
+                    (CallState::None, _) => TokenKind::Call,
+                    (CallState::Opened(_), _) => TokenKind::Argument,
+                    (CallState::Closed, _) => TokenKind::Punctuation,
+                },
+            },
+        }
+    }
+
+    fn push_token(&mut self, kind: TokenKind, ch: char, ix: usize) {
+        let Some(tokens) = &mut self.tokens else {
+            return;
+        };
+        match tokens.last_mut() {
+            Some(last) if last.kind == kind && kind.joins() && last.span.end() == ix => {
+                last.span.push_ch(ch)
+            }
+            _ => tokens.push(Token {
+                kind,
+                span: Slice(ix, ch.len_utf8()),
+            }),
+        }
+    }
+
     fn next_block(&mut self) -> Result<(), String> {
         if self.current_block.is_none() {
             return Ok(());
@@ -188,7 +321,7 @@ impl Default for CallState {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Slice(usize, usize);
 
 impl Slice {
@@ -204,16 +337,34 @@ impl Slice {
         self.1
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.1 == 0
+    }
+
+    pub fn end(&self) -> usize {
+        self.0 + self.1
+    }
+
+    /// Text of the slice in `src`, the string it was parsed from.
+    pub fn as_str<'s>(&self, src: &'s str) -> &'s str {
+        &src[self.0..self.0 + self.1]
+    }
+
+    /// Moves the slice by `offset`, e.g. from value-relative to file-relative positions.
+    pub fn shifted(&self, offset: usize) -> Self {
+        Self(self.0 + offset, self.1)
+    }
+
     fn started_at(ix: usize) -> Self {
         Self(ix, 0)
     }
 
-    fn push_ch(&mut self) {
-        self.1 += 1;
+    fn push_ch(&mut self, ch: char) {
+        self.1 += ch.len_utf8();
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Block {
     InfoPortion {
         key: Slice,
@@ -230,14 +381,27 @@ pub enum Block {
 }
 
 impl Block {
+    /// The whole block, from its prefix to the closing `)` of a call.
+    pub fn span(&self) -> Slice {
+        let (start, end) = match self {
+            Self::InfoPortion { key, .. } => (key.0, key.end()),
+            Self::Call { function, args, .. } => (
+                function.0,
+                args.last().map(|x| x.end() + 1).unwrap_or(function.end()),
+            ),
+            Self::Chance { val } => (val.0, val.end()),
+        };
+        Slice(start - 1, end - start + 1)
+    }
+
     fn push_ch(&mut self, ch: char, state: &mut CallState) -> Result<(), String> {
         match self {
-            Self::InfoPortion { key, .. } => key.push_ch(),
+            Self::InfoPortion { key, .. } => key.push_ch(ch),
             Self::Chance { val } => {
                 if !ch.is_ascii_digit() {
                     return Err("Not a digit!".to_owned());
                 }
-                val.push_ch();
+                val.push_ch(ch);
             }
             Self::Call {
                 function,
@@ -249,7 +413,7 @@ impl Block {
                         *state = CallState::Opened(Slice::started_at(function.0 + function.1 + 1));
                     }
                     (CallState::None, _) => {
-                        function.push_ch();
+                        function.push_ch(ch);
                         *state = CallState::None;
                     }
                     (CallState::Opened(_), '(') => return Err("Call is already opened".to_owned()),
@@ -267,7 +431,7 @@ impl Block {
                         *state = CallState::Closed;
                     }
                     (CallState::Opened(mut x), _) => {
-                        x.push_ch();
+                        x.push_ch(ch);
                         *state = CallState::Opened(x);
                     }
 
@@ -279,7 +443,17 @@ impl Block {
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+/// Characters with a meaning in condlists or LTX, forbidden in names.
+const RESERVED: &[char] = &[
+    '{', '}', '%', ',', '+', '-', '~', '=', '!', '(', ')', ':', '|', ';', '[', ']',
+];
+
+/// Whether `name` can be used as an info portion, function, argument or output.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(char::is_whitespace) && !name.contains(RESERVED)
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Statement {
     condition: Option<Condition>,
     effects: Option<Effect>,
@@ -312,30 +486,73 @@ impl Statement {
     pub fn val(&self) -> Option<&Slice> {
         self.out.as_ref()
     }
+
+    pub(crate) fn parts_mut(
+        &mut self,
+    ) -> (
+        Option<&mut Vec<Block>>,
+        Option<&mut Vec<Block>>,
+        &mut Option<Slice>,
+    ) {
+        (
+            self.condition.as_mut().map(|x| &mut x.0),
+            self.effects.as_mut().map(|x| &mut x.0),
+            &mut self.out,
+        )
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Ast<'a> {
-    orig: &'a str,
+    orig: Cow<'a, str>,
     statements: Vec<Statement>,
 }
 
+/// An [`Ast`] owning its source, independent of the buffer it was parsed from.
+pub type OwnedAst = Ast<'static>;
+
 impl<'a> Ast<'a> {
     pub fn from(src: &'a str) -> Result<Self, String> {
         let mut parser = Parser::new(src);
-        for (i, char) in src.chars().enumerate() {
+        for (i, char) in src.char_indices() {
             parser.eat(&char, i)?;
         }
         parser.finish()
     }
 
+    /// Parses a source the `Ast` takes ownership of.
+    pub fn from_string(src: String) -> Result<OwnedAst, String> {
+        let statements = Ast::from(&src)?.statements;
+        Ok(Ast {
+            orig: Cow::Owned(src),
+            statements,
+        })
+    }
+
     fn empty(src: &'a str) -> Self {
         Self {
-            orig: src,
+            orig: Cow::Borrowed(src),
             statements: Default::default(),
         }
     }
 
+    /// Copies the source if it is borrowed. Statements are moved, since
+    /// their slices stay valid for the copy.
+    pub fn into_owned(self) -> OwnedAst {
+        Ast {
+            orig: Cow::Owned(self.orig.into_owned()),
+            statements: self.statements,
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.orig
+    }
+
+    pub(crate) fn parts_mut(&mut self) -> (&str, &mut Vec<Statement>) {
+        (&self.orig, &mut self.statements)
+    }
+
     pub fn statements(&self) -> &[Statement] {
         &self.statements
     }
@@ -349,6 +566,40 @@ impl<'a> Ast<'a> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn tokens() {
+        let src = "{+a !f(x:y) ~5} b %-c =g%, d";
+        let tokens = tokenize(src)
+            .unwrap()
+            .into_iter()
+            .filter(|x| x.kind != TokenKind::Whitespace)
+            .map(|x| (x.kind.name(), x.span.as_str(src)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                ("condition-delimiter", "{"),
+                ("info-set", "+a"),
+                ("negated-call", "!f"),
+                ("punctuation", "("),
+                ("argument", "x"),
+                ("punctuation", ":"),
+                ("argument", "y"),
+                ("punctuation", ")"),
+                ("chance", "~5"),
+                ("condition-delimiter", "}"),
+                ("output", "b"),
+                ("effect-delimiter", "%"),
+                ("info-clear", "-c"),
+                ("call", "=g"),
+                ("effect-delimiter", "%"),
+                ("separator", ","),
+                ("output", "d"),
+            ]
+        );
+        assert!(tokenize("{{+a}}").is_err());
+    }
+
     #[test]
     fn simple_value() {
         let result = Ast::from("Y").unwrap();
@@ -483,7 +734,7 @@ mod tests {
         assert_eq!(
             result,
             Ast {
-                orig: &src,
+                orig: Cow::Borrowed(src),
                 statements: vec![
                     Statement {
                         condition: Some(Condition(vec![
@@ -533,4 +784,232 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn owned() {
+        let owned = {
+            let src = String::from("{+a} X %=f(b)%, Y");
+            let ast = Ast::from(&src).unwrap();
+            ast.into_owned()
+        };
+        let statement = &owned.statements()[0];
+        assert_eq!(owned.slice_as_str(statement.val().unwrap()), "X");
+        assert_eq!(owned, Ast::from("{+a} X %=f(b)%, Y").unwrap());
+        assert_eq!(
+            Ast::from_string("{+a} X %=f(b)%, Y".to_owned()).unwrap(),
+            owned
+        );
+    }
+
+    /// The tree in the S-expression form of `tree-sitter test`.
+    fn sexp(ast: &Ast) -> String {
+        fn blocks(out: &mut String, node: &str, blocks: &[Block]) {
+            out.push_str(&format!(" ({node}"));
+            for block in blocks {
+                out.push_str(&match block {
+                    Block::InfoPortion { inverted, .. } => {
+                        let node = if *inverted { "info_clear" } else { "info_set" };
+                        format!(" ({node} (name))")
+                    }
+                    Block::Call { args, inverted, .. } => {
+                        let node = if *inverted { "negated_call" } else { "call" };
+                        let args = if args.is_empty() {
+                            String::new()
+                        } else {
+                            let args = args.iter().filter(|x| !x.is_empty());
+                            format!(" (arguments{})", " (argument)".repeat(args.count()))
+                        };
+                        format!(" ({node} (function){args})")
+                    }
+                    Block::Chance { .. } => " (chance (number))".to_owned(),
+                });
+            }
+            out.push(')');
+        }
+
+        let mut out = String::from("(condlist");
+        for statement in ast.statements() {
+            let (condition, effects) = (statement.conditions(), statement.effects());
+            if condition.is_none() && effects.is_none() && statement.val().is_none() {
+                continue;
+            }
+            out.push_str(" (statement");
+            if let Some(x) = condition {
+                blocks(&mut out, "condition", x.blocks());
+            }
+            if statement.val().is_some() {
+                out.push_str(" (output)");
+            }
+            if let Some(x) = effects {
+                blocks(&mut out, "effects", x.blocks());
+            }
+            out.push(')');
+        }
+        out.push(')');
+        out
+    }
+
+    /// A case of the `tree-sitter-condlist` corpus.
+    struct Case {
+        title: String,
+        src: String,
+        /// The tree as an S-expression.
+        expected: String,
+    }
+
+    /// Every case of the `tree-sitter-condlist` corpus, by file name.
+    fn corpus() -> Vec<(String, Vec<Case>)> {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tree-sitter-condlist/test/corpus");
+        let mut files = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|x| x.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        // Each case is a title between `=` rules, the source, a `-` rule and
+        // the expected tree.
+        let rule = |x: &str, ch| x.len() > 3 && x.chars().all(|c| c == ch);
+        files
+            .iter()
+            .map(|file| {
+                let text = std::fs::read_to_string(file).unwrap();
+                let mut lines = text.lines().peekable();
+                let mut cases = Vec::new();
+                while let Some(line) = lines.next() {
+                    if !rule(line, '=') {
+                        continue;
+                    }
+                    let title = lines.next().unwrap().to_owned();
+                    lines.next();
+                    let src = lines
+                        .by_ref()
+                        .take_while(|x| !rule(x, '-'))
+                        .collect::<String>();
+                    let mut expected = String::new();
+                    while let Some(line) = lines.next_if(|x| !rule(x, '=')) {
+                        expected.push_str(line);
+                    }
+                    cases.push(Case {
+                        title,
+                        src: src.trim().to_owned(),
+                        expected,
+                    });
+                }
+                let name = file.file_stem().unwrap().to_string_lossy().into_owned();
+                (name, cases)
+            })
+            .collect()
+    }
+
+    /// The corpus of `tree-sitter-condlist` must parse the same with the
+    /// grammar and with [`Ast::from`].
+    #[test]
+    fn tree_sitter_corpus() {
+        let normalize = |x: &str| x.split_whitespace().collect::<Vec<_>>().join(" ");
+        let corpus = corpus();
+        assert!(!corpus.is_empty());
+        for (file, cases) in corpus {
+            assert!(!cases.is_empty(), "{file} has no cases");
+            for case in cases {
+                let ast = Ast::from(&case.src).unwrap();
+                assert_eq!(
+                    normalize(&sexp(&ast)),
+                    normalize(&case.expected),
+                    "{}",
+                    case.title
+                );
+            }
+        }
+    }
+
+    /// Runs `tree-sitter test` on a copy of `tree-sitter-condlist`, so that
+    /// the grammar is checked against the same corpus, and checks that it
+    /// passed every case [`tree_sitter_corpus`] read. Uses `$TREE_SITTER`,
+    /// which must run, or else `tree-sitter` on the path, skipping the test
+    /// when it is missing.
+    #[test]
+    fn tree_sitter_grammar() {
+        use std::path::Path;
+        use std::process::Command;
+
+        fn copy(from: &Path, to: &Path) {
+            std::fs::create_dir_all(to).unwrap();
+            for entry in std::fs::read_dir(from).unwrap() {
+                let path = entry.unwrap().path();
+                let target = to.join(path.file_name().unwrap());
+                if path.is_dir() {
+                    copy(&path, &target);
+                } else {
+                    std::fs::copy(&path, &target).unwrap();
+                }
+            }
+        }
+
+        let cli = match std::env::var("TREE_SITTER") {
+            Ok(x) => {
+                let found = Command::new(&x).arg("--version").output();
+                assert!(found.is_ok(), "TREE_SITTER={x} does not run");
+                x
+            }
+            Err(_) => {
+                if Command::new("tree-sitter")
+                    .arg("--version")
+                    .output()
+                    .is_err()
+                {
+                    eprintln!("skipped, tree-sitter not found");
+                    return;
+                }
+                "tree-sitter".to_owned()
+            }
+        };
+        let grammar = Path::new(env!("CARGO_MANIFEST_DIR")).join("tree-sitter-condlist");
+        let dir = std::env::temp_dir().join(format!("tree-sitter-condlist-{}", std::process::id()));
+        for x in ["grammar.js", "package.json", "queries", "test"] {
+            let from = grammar.join(x);
+            if from.is_dir() {
+                copy(&from, &dir.join(x));
+            } else {
+                std::fs::create_dir_all(&dir).unwrap();
+                std::fs::copy(&from, dir.join(x)).unwrap();
+            }
+        }
+        let run = |args: &[&str]| {
+            let out = Command::new(&cli)
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&out.stdout).into_owned();
+            assert!(
+                out.status.success(),
+                "tree-sitter {}: {}{}",
+                args.join(" "),
+                stdout,
+                String::from_utf8_lossy(&out.stderr)
+            );
+            stdout
+        };
+        run(&["generate"]);
+        let report = run(&["test"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Passed cases are listed as `N. ✓ title` below `file:`, coloured.
+        let passed = report
+            .lines()
+            .filter(|x| x.contains('✓'))
+            .map(|x| x.replace("\x1b[32m", "").replace("\x1b[0m", ""))
+            .collect::<Vec<_>>();
+        for (file, cases) in corpus() {
+            assert!(report.contains(&format!("{file}:")), "{file} not tested");
+            for Case { title, .. } in cases {
+                assert!(
+                    passed
+                        .iter()
+                        .any(|x| x.trim_end().ends_with(&format!("✓ {title}"))),
+                    "{file}: {title} not passed by the grammar"
+                );
+            }
+        }
+    }
 }
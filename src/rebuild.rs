@@ -1,3 +1,4 @@
+use crate::order::{LUA_ROLL, lua_chance};
 use crate::parser::{Ast, Block, Condition, Effect, Slice};
 
 #[derive(Debug, PartialEq)]
@@ -35,11 +36,25 @@ trait IntoLua {
     fn to_lua(&self, ast: &Ast, ix: usize, indent: usize) -> (String, Metadata);
 }
 
+/// Compiles a condlist to the Lua code `xr_logic` would run for it, in the
+/// order described in [`crate::order`].
+pub fn to_lua(ast: &Ast) -> String {
+    ast.to_lua(ast, 0, 0).0
+}
+
 impl IntoLua for Ast<'_> {
     fn to_lua(&self, _: &Ast, ix: usize, indent: usize) -> (String, Metadata) {
         let mut out = IndentStr::new();
         let mut metadata = Metadata::default();
 
+        let chance = self.statements().iter().any(|x| {
+            x.conditions()
+                .is_some_and(|x| x.blocks().iter().any(|b| matches!(b, Block::Chance { .. })))
+        });
+        if chance {
+            out.push_str(LUA_ROLL, indent);
+        }
+
         for statement in self.statements() {
             let has_conds = statement
                 .conditions()
@@ -132,7 +147,7 @@ impl IntoLua for Effect {
             .fold((IndentStr::new(), Metadata::default()), |mut acc, b| {
                 acc.1
                     .0
-                    .push((Slice::new(ix + acc.0.0.len(), b.len()), Tag::Condition));
+                    .push((Slice::new(ix + acc.0.0.len(), b.len()), Tag::Effect));
                 acc.0.push_str(&b, indent);
                 acc
             });
@@ -173,9 +188,7 @@ impl IntoLua for Condition {
                     }
                     lua_val
                 }
-                Block::Chance { val } => {
-                    format!("math.random(1, 100) > {}\n", ast.slice_as_str(val))
-                }
+                Block::Chance { val } => format!("{}\n", lua_chance(ast.slice_as_str(val))),
             })
             .fold((IndentStr::new(), Metadata::default()), |mut acc, b| {
                 if !acc.0.is_empty() {
@@ -211,7 +224,6 @@ mod tests {
     fn effect_call() {
         let ast = Ast::from("Y %=A%").unwrap();
         let val = ast.to_lua(&ast, 0, 0);
-        dbg!(&val.0);
         assert_eq!(
             val,
             (
@@ -232,8 +244,13 @@ mod tests {
         let src = "{=A(a1:a2) !B +C -D ~30} X %=E(e1) +F -G%, Y";
         let ast = Ast::from(src).unwrap();
 
-        let (lua, meta) = ast.to_lua(&ast, 0, 0);
-        println!("{}", lua);
+        let lua = to_lua(&ast);
+        assert!(
+            lua.contains("if xr_conditions.A(\"a1\",\"a2\")\n"),
+            "{}",
+            lua
+        );
+        assert!(lua.contains("xr_effects.E(\"e1\")\n"), "{}", lua);
     }
 
     #[test]
@@ -241,7 +258,7 @@ mod tests {
         let src = "{=A(a1:a2) !B +C -D ~30} X %=E(e1) +F -G%, {=A(a1:a2) !B +C -D ~30} Y, B";
         let ast = Ast::from(src).unwrap();
 
-        let (lua, meta) = ast.to_lua(&ast, 0, 0);
-        println!("{}", lua);
+        let lua = to_lua(&ast);
+        assert_eq!(lua.matches("return ").count(), 3, "{}", lua);
     }
 }
@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::ltx::Ltx;
+use crate::parser::{Ast, Block, Slice, is_valid_name};
+use crate::tree::Tree;
+use crate::visit::{Context, Visit};
+use crate::xref::InfoIndex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// An info portion key, in condlists and dialog XML.
+    Info,
+    /// The function of a `Block::Call`, in conditions and effects.
+    Function,
+    /// A logic section. Only files defining the section are touched, since
+    /// section names are local to their logic file.
+    Section,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub span: Slice,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FileEdits {
+    pub path: PathBuf,
+    /// Sorted and not overlapping.
+    pub edits: Vec<Edit>,
+}
+
+/// Computes the edits renaming `from` to `to` across `tree`.
+pub fn rename(
+    tree: &Tree,
+    index: &InfoIndex,
+    target: Target,
+    from: &str,
+    to: &str,
+) -> Result<Vec<FileEdits>, String> {
+    if !is_valid_name(to) {
+        return Err(format!("`{}` is not a valid name", to));
+    }
+
+    let mut spans: BTreeMap<PathBuf, Vec<Slice>> = BTreeMap::new();
+    match target {
+        Target::Info => {
+            for reference in index.references(from) {
+                spans
+                    .entry(reference.file.clone())
+                    .or_default()
+                    .push(reference.span);
+            }
+        }
+        Target::Function => {
+            for file in tree.files().iter().filter(|x| x.is_ltx()) {
+                let Ok(ltx) = Ltx::from(&file.text) else {
+                    continue;
+                };
+                let found = condlist_spans(&ltx, Target::Function, from);
+                if !found.is_empty() {
+                    spans.insert(file.path.clone(), found);
+                }
+            }
+        }
+        Target::Section => {
+            for file in tree.files().iter().filter(|x| x.is_ltx()) {
+                let Ok(ltx) = Ltx::from(&file.text) else {
+                    continue;
+                };
+                if ltx.section(from).is_some() {
+                    spans.insert(file.path.clone(), section_spans(&ltx, from));
+                }
+            }
+        }
+    }
+
+    Ok(spans
+        .into_iter()
+        .map(|(path, mut spans)| {
+            spans.sort_by_key(|x| x.index());
+            spans.dedup();
+            FileEdits {
+                path,
+                edits: spans
+                    .into_iter()
+                    .map(|span| Edit {
+                        span,
+                        text: to.to_owned(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Collects the file positions of every function or output named `name`.
+struct Finder<'n> {
+    target: Target,
+    name: &'n str,
+    offset: usize,
+    found: Vec<Slice>,
+}
+
+impl Visit for Finder<'_> {
+    fn visit_block(&mut self, ast: &Ast, block: &Block, _: Context) {
+        if let (Target::Function, Block::Call { function, .. }) = (self.target, block)
+            && ast.slice_as_str(function) == self.name
+        {
+            self.found.push(function.shifted(self.offset));
+        }
+    }
+
+    fn visit_output(&mut self, ast: &Ast, out: &Slice, _: usize) {
+        if self.target == Target::Section && ast.slice_as_str(out) == self.name {
+            self.found.push(out.shifted(self.offset));
+        }
+    }
+}
+
+fn condlist_spans(ltx: &Ltx, target: Target, name: &str) -> Vec<Slice> {
+    let mut finder = Finder {
+        target,
+        name,
+        offset: 0,
+        found: Vec::new(),
+    };
+    for condlist in ltx.condlists() {
+        let Ok(ast) = ltx.parse(&condlist) else {
+            continue;
+        };
+        finder.offset = condlist.value.index();
+        finder.visit_ast(&ast);
+    }
+    finder.found
+}
+
+fn section_spans(ltx: &Ltx, section: &str) -> Vec<Slice> {
+    let mut out = Vec::new();
+    for x in ltx.sections() {
+        out.extend(
+            std::iter::once(x.name())
+                .chain(x.parents())
+                .filter(|x| ltx.slice_as_str(x) == section),
+        );
+    }
+    out.extend(condlist_spans(ltx, Target::Section, section));
+    out
+}
+
+/// Applies sorted, non-overlapping edits to `text`.
+pub fn apply(text: &str, edits: &[Edit]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for edit in edits {
+        out.push_str(&text[last..edit.span.index()]);
+        out.push_str(&edit.text);
+        last = edit.span.end();
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::tree::{Encoding, SourceFile};
+
+    const LOGIC: &str = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+q1_done =is_alive(wolf)} walker@2 %+q1_reward =hit(wolf)%
+[walker@2]:walker@1
+on_info = {!is_alive(wolf)} walker@1
+";
+
+    const OTHER: &str = "\
+[walker@1]
+on_info = {=is_alive} walker@1
+";
+
+    const DIALOG: &str = "<dialog><has_info>q1_done</has_info></dialog>";
+
+    fn tree() -> Tree {
+        let mut tree = Tree::default();
+        for (path, text) in [("a.ltx", LOGIC), ("b.ltx", OTHER), ("d.xml", DIALOG)] {
+            tree.push(SourceFile {
+                path: path.into(),
+                text: text.to_owned(),
+                encoding: Encoding::Utf8,
+            });
+        }
+        tree
+    }
+
+    fn renamed(target: Target, from: &str, to: &str) -> Vec<(PathBuf, String)> {
+        let tree = tree();
+        let index = InfoIndex::from_tree(&tree);
+        rename(&tree, &index, target, from, to)
+            .unwrap()
+            .into_iter()
+            .map(|x| {
+                let text = &tree.file(&x.path).unwrap().text;
+                (x.path, apply(text, &x.edits))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn info() {
+        let out = renamed(Target::Info, "q1_done", "q1_finished");
+        assert_eq!(out.len(), 2);
+        assert!(out[0].1.contains("{+q1_finished =is_alive(wolf)}"));
+        assert_eq!(out[1].0, Path::new("d.xml"));
+        assert_eq!(
+            out[1].1,
+            "<dialog><has_info>q1_finished</has_info></dialog>"
+        );
+    }
+
+    #[test]
+    fn function() {
+        let out = renamed(Target::Function, "is_alive", "is_npc_alive");
+        assert_eq!(out.len(), 2);
+        assert!(out[0].1.contains("{+q1_done =is_npc_alive(wolf)}"));
+        assert!(out[0].1.contains("{!is_npc_alive(wolf)}"));
+        assert_eq!(out[1].1, "[walker@1]\non_info = {=is_npc_alive} walker@1\n");
+    }
+
+    #[test]
+    fn section() {
+        let out = renamed(Target::Section, "walker@2", "walker@end");
+        assert_eq!(out.len(), 1);
+        assert!(out[0].1.contains("[walker@end]:walker@1"));
+        assert!(out[0].1.contains("walker@end %+q1_reward"));
+
+        let out = renamed(Target::Section, "walker@1", "walker@start");
+        assert_eq!(out.len(), 2);
+        assert!(out[0].1.contains("active = walker@start\n"));
+        assert!(out[0].1.contains("[walker@2]:walker@start"));
+    }
+
+    #[test]
+    fn invalid_name() {
+        let tree = tree();
+        let index = InfoIndex::from_tree(&tree);
+        assert!(rename(&tree, &index, Target::Info, "q1_done", "q1 done").is_err());
+        assert!(rename(&tree, &index, Target::Info, "q1_done", "+q1").is_err());
+    }
+}
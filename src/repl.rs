@@ -0,0 +1,223 @@
+//! A line-based session around [`evaluate`]: commands starting with `:`
+//! change the simulated world, any other line is evaluated as a condlist
+//! against it. Effects stay applied, so consecutive lines see each other's
+//! info portions.
+
+use crate::eval::State;
+use crate::parser::Ast;
+use crate::trace::{Step, trace};
+
+pub const HELP: &str = "\
+:give INFO...         give info portions
+:take INFO...         disable info portions
+:stub F [A B] = BOOL  result of condition F, for any or the given arguments
+:seed N               reseed the random generator used by ~N
+:state                show info portions, stubs and effect calls
+:trace                show every evaluation so far and what it changed
+:reset                start over with the world the session began with
+:help                 show this help
+:quit, :q             leave
+anything else         evaluate it as a condlist";
+
+#[derive(Debug, Default)]
+pub struct Repl {
+    pub state: State,
+    /// The world given to [`Repl::new`], restored by `:reset`.
+    initial: State,
+    /// Evaluated condlists and their traces, since the last `:reset`.
+    history: Vec<(String, Step)>,
+}
+
+impl Repl {
+    pub fn new(state: State) -> Self {
+        Self {
+            initial: state.clone(),
+            state,
+            history: Vec::new(),
+        }
+    }
+
+    /// Handles one line and returns what to print, or `None` on `:quit`.
+    pub fn line(&mut self, line: &str) -> Option<Result<String, String>> {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix(':') else {
+            return Some(self.evaluate(line));
+        };
+        let (command, rest) = command
+            .split_once(char::is_whitespace)
+            .unwrap_or((command, ""));
+        let rest = rest.trim();
+        Some(match command {
+            "give" | "take" if rest.is_empty() => {
+                Err(format!(":{} needs an info portion", command))
+            }
+            "give" => {
+                self.state
+                    .infos
+                    .extend(rest.split_whitespace().map(|x| x.to_owned()));
+                Ok(String::new())
+            }
+            "take" => {
+                for info in rest.split_whitespace() {
+                    self.state.infos.remove(info);
+                }
+                Ok(String::new())
+            }
+            "stub" => self.state.stub_str(rest).map(|()| String::new()),
+            "seed" => rest
+                .parse()
+                .map(|x| {
+                    self.state.seed(x);
+                    String::new()
+                })
+                .map_err(|e| format!(":seed {}: {}", rest, e)),
+            "state" => Ok(self.describe()),
+            "trace" => Ok(self.history()),
+            "reset" => {
+                self.state = self.initial.clone();
+                self.history.clear();
+                Ok(String::new())
+            }
+            "help" => Ok(HELP.to_owned()),
+            "quit" | "q" => return None,
+            x => Err(format!("unknown command :{}, see :help", x)),
+        })
+    }
+
+    /// The chosen output, then the effects of its statement, one per line.
+    fn evaluate(&mut self, src: &str) -> Result<String, String> {
+        if src.is_empty() {
+            return Ok(String::new());
+        }
+        let ast = Ast::from(src)?;
+        let step = trace(&ast, &mut self.state)?;
+        let Some(i) = step.outcome.statement else {
+            self.history.push((src.to_owned(), step));
+            return Ok("nil (no statement passes)".to_owned());
+        };
+        let mut out = format!(
+            "{} (statement {})",
+            step.outcome.output.as_deref().unwrap_or("nil"),
+            i + 1
+        );
+        for action in &step.actions {
+            out.push_str(&format!("\n  {}", action));
+        }
+        self.history.push((src.to_owned(), step));
+        Ok(out)
+    }
+
+    /// One paragraph per evaluation: the condlist, the chosen output and the
+    /// changes to the world.
+    fn history(&self) -> String {
+        let mut out = Vec::new();
+        for (i, (src, step)) in self.history.iter().enumerate() {
+            let mut entry = format!(
+                "{}. {}\n   -> {}",
+                i + 1,
+                src,
+                step.outcome.output.as_deref().unwrap_or("nil")
+            );
+            if !step.diff.is_empty() {
+                entry.push_str(&format!("\n   changed: {}", step.diff));
+            }
+            out.push(entry);
+        }
+        out.join("\n")
+    }
+
+    fn describe(&self) -> String {
+        let infos = self.state.infos.iter().cloned().collect::<Vec<_>>();
+        let stubs = self
+            .state
+            .stubs()
+            .map(|(f, args, value)| match args {
+                Some(args) => format!("{}({})={}", f, args.join(":"), value),
+                None => format!("{}={}", f, value),
+            })
+            .collect::<Vec<_>>();
+        let calls = self
+            .state
+            .calls
+            .iter()
+            .map(|(f, args)| format!("{}({})", f, args.join(":")))
+            .collect::<Vec<_>>();
+        format!(
+            "infos: {}\nstubs: {}\ncalls: {}",
+            infos.join(" "),
+            stubs.join(" "),
+            calls.join(" ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(repl: &mut Repl, line: &str) -> String {
+        repl.line(line).unwrap().unwrap()
+    }
+
+    #[test]
+    fn session() {
+        let mut repl = Repl::default();
+        let logic = "{+esc_done =is_alive(wolf)} walker@2 %+met =give_task(t)%, walker@1";
+        assert_eq!(run(&mut repl, logic), "walker@1 (statement 2)");
+
+        run(&mut repl, ":give esc_done");
+        assert!(repl.line(logic).unwrap().is_err());
+        run(&mut repl, ":stub is_alive wolf = true");
+        assert_eq!(
+            run(&mut repl, logic),
+            "walker@2 (statement 1)\n  +met\n  =give_task(t)"
+        );
+        assert_eq!(
+            run(&mut repl, ":state"),
+            "infos: esc_done met\nstubs: is_alive(wolf)=true\ncalls: give_task(t)"
+        );
+
+        assert_eq!(
+            run(&mut repl, ":trace"),
+            format!(
+                "1. {logic}\n   -> walker@1\n\
+                 2. {logic}\n   -> walker@2\n   changed: +met =give_task(t)"
+            )
+        );
+
+        run(&mut repl, ":take esc_done met");
+        assert_eq!(run(&mut repl, "{+met} x"), "nil (no statement passes)");
+        run(&mut repl, ":reset");
+        assert_eq!(run(&mut repl, ":state"), "infos: \nstubs: \ncalls: ");
+        assert_eq!(run(&mut repl, ":trace"), "");
+    }
+
+    #[test]
+    fn commands() {
+        let mut repl = Repl::default();
+        assert!(repl.line(":seed x").unwrap().is_err());
+        assert!(repl.line(":give").unwrap().is_err());
+        assert!(repl.line(":frobnicate").unwrap().is_err());
+        assert!(repl.line("{{+a}").unwrap().is_err());
+        assert_eq!(run(&mut repl, ""), "");
+        assert!(repl.line(":quit").is_none());
+
+        run(&mut repl, ":seed 7");
+        let first = run(&mut repl, "{~50} a, b");
+        run(&mut repl, ":seed 7");
+        assert_eq!(run(&mut repl, "{~50} a, b"), first);
+        assert!(repl.line(":q").is_none());
+    }
+
+    #[test]
+    fn reset_keeps_the_initial_world() {
+        let mut state = State::default();
+        state.infos.insert("a".to_owned());
+        state.stub("f", None, true);
+        let mut repl = Repl::new(state);
+        run(&mut repl, ":take a");
+        run(&mut repl, ":stub f = false");
+        run(&mut repl, ":reset");
+        assert_eq!(run(&mut repl, "{+a =f} x"), "x (statement 1)");
+    }
+}
@@ -0,0 +1,503 @@
+//! Behaviour tests for logic: a starting world, a condlist and what
+//! evaluating it must do. Scenarios are written in TOML with the `scenario`
+//! feature:
+//!
+//! ```toml
+//! [[scenario]]
+//! name = "guard lets the actor pass"
+//! ltx = "configs/scripts/esc/guard.ltx"  # relative to the scenario file
+//! section = "walker@1"
+//! key = "on_info"                         # or: condlist = "{+a} x, y"
+//! infos = ["esc_done"]
+//! stubs = { "is_alive(esc_wolf)" = false, see_actor = true }
+//! seed = 3
+//!
+//! [scenario.expect]
+//! output = "walker@2"                     # "nil" when nothing is chosen
+//! statement = 0
+//! infos = ["esc_done", "met"]             # every info portion afterwards
+//! calls = ["give_task(esc_task)"]         # every effect call, in order
+//! ```
+//!
+//! With `ltx` alone, the logic of the file is simulated instead, see
+//! [`crate::sim`]:
+//!
+//! ```toml
+//! [[scenario]]
+//! name = "guard leaves after the talk"
+//! ltx = "configs/scripts/esc/guard.ltx"
+//! ticks = 10
+//! events = { 2 = ["+talked"], 5 = ["see_actor=true"] }
+//!
+//! [scenario.expect]
+//! section = "remark@3"                    # "nil" when the logic ended
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::coverage::Coverage;
+use crate::eval::State;
+use crate::ltx::Ltx;
+use crate::parser::Ast;
+use crate::sim::{Simulation, Timeline};
+use crate::trace::trace;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Condlist(String),
+    /// The value of `key` in `section`, in an LTX file.
+    Entry {
+        file: PathBuf,
+        section: String,
+        key: String,
+    },
+    /// The logic of an LTX file, run for [`Scenario::ticks`].
+    Logic(PathBuf),
+}
+
+/// What must hold after the evaluation. `None` is not checked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Expect {
+    /// The chosen section, `nil` if there is none.
+    pub output: Option<String>,
+    pub statement: Option<usize>,
+    /// The active section at the end of a simulation, `nil` if the logic
+    /// ended.
+    pub section: Option<String>,
+    pub infos: Option<Vec<String>>,
+    /// Effect calls as `f(a:b)`.
+    pub calls: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub source: Source,
+    pub infos: Vec<String>,
+    /// Stubs as accepted by [`State::stub_str`].
+    pub stubs: Vec<String>,
+    pub seed: u64,
+    /// Ticks after the start of a simulation.
+    pub ticks: usize,
+    pub timeline: Timeline,
+    pub expect: Expect,
+}
+
+impl Scenario {
+    pub fn new(name: &str, source: Source) -> Self {
+        Self {
+            name: name.to_owned(),
+            source,
+            infos: Vec::new(),
+            stubs: Vec::new(),
+            seed: 0,
+            ticks: 0,
+            timeline: Timeline::default(),
+            expect: Expect::default(),
+        }
+    }
+
+    /// Evaluates the scenario and returns every expectation it misses.
+    /// Relative LTX paths are resolved from `dir`.
+    pub fn run(&self, dir: &Path) -> Vec<String> {
+        self.run_covered(dir, &mut Coverage::default())
+    }
+
+    /// [`Scenario::run`], adding the LTX files it evaluates to `coverage`.
+    pub fn run_covered(&self, dir: &Path, coverage: &mut Coverage) -> Vec<String> {
+        match self.try_run(dir, coverage) {
+            Ok(x) => x,
+            Err(e) => vec![e],
+        }
+    }
+
+    fn try_run(&self, dir: &Path, coverage: &mut Coverage) -> Result<Vec<String>, String> {
+        let mut state = State::with_seed(self.seed);
+        state.infos.extend(self.infos.iter().cloned());
+        for stub in &self.stubs {
+            state.stub_str(stub)?;
+        }
+
+        let mut read = |file: &Path| {
+            let path = dir.join(file);
+            let text =
+                std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            coverage.add_file(&path, &text);
+            Ok::<_, String>((path, text))
+        };
+        let (outcome, section) = match &self.source {
+            Source::Condlist(src) => (Some(trace(&Ast::from(src)?, &mut state)?.outcome), None),
+            Source::Entry { file, section, key } => {
+                let (path, text) = read(file)?;
+                let ltx = Ltx::from(&text)?;
+                let condlist = ltx
+                    .condlist(section, key)
+                    .ok_or_else(|| format!("{}: no `{}` in [{}]", file.display(), key, section))?;
+                let step = trace(&ltx.parse(&condlist)?, &mut state)?;
+                coverage.record(&path, &condlist.value, &step);
+                (Some(step.outcome), None)
+            }
+            Source::Logic(file) => {
+                let (path, text) = read(file)?;
+                let ltx = Ltx::from(&text)?;
+                let mut sim = Simulation::new(&ltx, state);
+                let result = sim.run(&self.timeline, self.ticks);
+                for x in sim.log.iter().flat_map(|x| &x.evaluations) {
+                    coverage.record(&path, &x.value, &x.step);
+                }
+                result?;
+                state = sim.state;
+                (None, Some(sim.section))
+            }
+        };
+
+        let mut out = Vec::new();
+        let mut check = |what: &str, expected: String, actual: String| {
+            if expected != actual {
+                out.push(format!("{}: expected {}, got {}", what, expected, actual));
+            }
+        };
+        if let (Some(x), Some(outcome)) = (&self.expect.output, &outcome) {
+            let actual = outcome.output.clone();
+            check("output", x.clone(), actual.unwrap_or("nil".to_owned()));
+        }
+        if let (Some(x), Some(outcome)) = (self.expect.statement, &outcome) {
+            let actual = outcome.statement.map(|x| x.to_string());
+            check(
+                "statement",
+                x.to_string(),
+                actual.unwrap_or("none".to_owned()),
+            );
+        }
+        if let (Some(x), Some(section)) = (&self.expect.section, section) {
+            check("section", x.clone(), section.unwrap_or("nil".to_owned()));
+        }
+        if let Some(x) = &self.expect.infos {
+            let mut expected = x.clone();
+            expected.sort();
+            expected.dedup();
+            let actual = state.infos.iter().cloned().collect::<Vec<_>>();
+            check("infos", list(&expected), list(&actual));
+        }
+        if let Some(x) = &self.expect.calls {
+            let actual = state
+                .calls
+                .iter()
+                .map(|(f, args)| format!("{}({})", f, args.join(":")))
+                .collect::<Vec<_>>();
+            check("calls", list(x), list(&actual));
+        }
+        Ok(out)
+    }
+}
+
+fn list(items: &[String]) -> String {
+    format!("[{}]", items.join(", "))
+}
+
+/// Reads the `[[scenario]]` tables of a TOML file. Unknown keys are errors,
+/// so that a typo does not silently skip a check.
+#[cfg(feature = "scenario")]
+pub fn from_toml(text: &str) -> Result<Vec<Scenario>, String> {
+    use crate::sim::Event;
+    use toml::{Table, Value};
+
+    fn string(table: &Table, key: &str) -> Result<Option<String>, String> {
+        match table.get(key) {
+            None => Ok(None),
+            Some(Value::String(x)) => Ok(Some(x.clone())),
+            Some(_) => Err(format!("`{}` must be a string", key)),
+        }
+    }
+
+    fn strings(table: &Table, key: &str) -> Result<Option<Vec<String>>, String> {
+        let Some(value) = table.get(key) else {
+            return Ok(None);
+        };
+        let error = || format!("`{}` must be an array of strings", key);
+        let items = value.as_array().ok_or_else(error)?;
+        items
+            .iter()
+            .map(|x| x.as_str().map(|x| x.to_owned()).ok_or_else(error))
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    fn integer(table: &Table, key: &str) -> Result<Option<u64>, String> {
+        match table.get(key) {
+            None => Ok(None),
+            Some(Value::Integer(x)) if *x >= 0 => Ok(Some(*x as u64)),
+            Some(_) => Err(format!("`{}` must be a non-negative integer", key)),
+        }
+    }
+
+    fn known(table: &Table, keys: &[&str]) -> Result<(), String> {
+        match table.keys().find(|x| !keys.contains(&x.as_str())) {
+            Some(x) => Err(format!("unknown key `{}`", x)),
+            None => Ok(()),
+        }
+    }
+
+    fn scenario(table: &Table) -> Result<Scenario, String> {
+        known(
+            table,
+            &[
+                "name", "condlist", "ltx", "section", "key", "infos", "stubs", "seed", "ticks",
+                "events", "expect",
+            ],
+        )?;
+        let name = string(table, "name")?.ok_or("missing `name`")?;
+        let source = match (
+            string(table, "condlist")?,
+            string(table, "ltx")?,
+            string(table, "section")?,
+            string(table, "key")?,
+        ) {
+            (Some(x), None, None, None) => Source::Condlist(x),
+            (None, Some(file), Some(section), Some(key)) => Source::Entry {
+                file: file.into(),
+                section,
+                key,
+            },
+            (None, Some(file), None, None) => Source::Logic(file.into()),
+            _ => {
+                return Err("expected `condlist`, `ltx` or `ltx`, `section` and `key`".to_owned());
+            }
+        };
+        // Keys that only make sense for one kind of scenario.
+        let expect = table.get("expect").and_then(|x| x.as_table());
+        let (keys, expected, what) = match source {
+            Source::Logic(_) => (&[][..], &["output", "statement"][..], "single condlists"),
+            _ => (&["ticks", "events"][..], &["section"][..], "simulations"),
+        };
+        let misplaced = keys.iter().find(|x| table.contains_key(**x)).or_else(|| {
+            let expect = expect?;
+            expected.iter().find(|x| expect.contains_key(**x))
+        });
+        if let Some(x) = misplaced {
+            return Err(format!("`{}` is only for {}", x, what));
+        }
+
+        let mut out = Scenario::new(&name, source);
+        out.infos = strings(table, "infos")?.unwrap_or_default();
+        out.seed = integer(table, "seed")?.unwrap_or_default();
+        out.ticks = integer(table, "ticks")?.unwrap_or_default() as usize;
+        if let Some(events) = table.get("events") {
+            let events = events.as_table().ok_or("`events` must be a table")?;
+            for (tick, _) in events {
+                let at = tick
+                    .parse()
+                    .map_err(|_| format!("event tick `{}` must be a number", tick))?;
+                for event in strings(events, tick)?.unwrap_or_default() {
+                    out.timeline.at(at, Event::parse(&event)?);
+                }
+            }
+        }
+        if let Some(stubs) = table.get("stubs") {
+            let stubs = stubs.as_table().ok_or("`stubs` must be a table")?;
+            for (call, value) in stubs {
+                let value = value
+                    .as_bool()
+                    .ok_or_else(|| format!("stub `{}` must be a boolean", call))?;
+                out.stubs.push(format!("{}={}", call, value));
+            }
+        }
+        if let Some(expect) = table.get("expect") {
+            let expect = expect.as_table().ok_or("`expect` must be a table")?;
+            known(
+                expect,
+                &["output", "statement", "section", "infos", "calls"],
+            )?;
+            out.expect = Expect {
+                output: string(expect, "output")?,
+                statement: integer(expect, "statement")?.map(|x| x as usize),
+                section: string(expect, "section")?,
+                infos: strings(expect, "infos")?,
+                calls: strings(expect, "calls")?,
+            };
+        }
+        Ok(out)
+    }
+
+    let table = text.parse::<Table>().map_err(|e| e.to_string())?;
+    known(&table, &["scenario"])?;
+    let Some(scenarios) = table.get("scenario") else {
+        return Ok(Vec::new());
+    };
+    let scenarios = scenarios
+        .as_array()
+        .ok_or("`scenario` must be an array of tables")?;
+    scenarios
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let table = x
+                .as_table()
+                .ok_or("`scenario` must be an array of tables")?;
+            scenario(table).map_err(|e| format!("scenario {}: {}", i + 1, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expectations() {
+        let mut scenario = Scenario::new(
+            "met",
+            Source::Condlist("{+a =f(x)} b %+c =g(1:2)%, d".to_owned()),
+        );
+        scenario.infos = vec!["a".to_owned()];
+        scenario.stubs = vec!["f=true".to_owned()];
+        scenario.expect = Expect {
+            output: Some("b".to_owned()),
+            statement: Some(0),
+            section: None,
+            infos: Some(vec!["c".to_owned(), "a".to_owned()]),
+            calls: Some(vec!["g(1:2)".to_owned()]),
+        };
+        assert_eq!(scenario.run(Path::new(".")), Vec::<String>::new());
+
+        scenario.stubs = vec!["f(x)=false".to_owned()];
+        assert_eq!(
+            scenario.run(Path::new(".")),
+            vec![
+                "output: expected b, got d",
+                "statement: expected 0, got 1",
+                "infos: expected [a, c], got [a]",
+                "calls: expected [g(1:2)], got []",
+            ]
+        );
+
+        scenario.stubs.clear();
+        assert_eq!(
+            scenario.run(Path::new(".")),
+            vec!["No stub for condition `f(x)`"]
+        );
+    }
+
+    #[test]
+    fn ltx_entry() {
+        let dir = std::env::temp_dir().join("condlist-scenario-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("logic.ltx"),
+            "[walker@1]\non_info = {+a} walker@2, nil\n",
+        )
+        .unwrap();
+
+        let source = |key: &str| Source::Entry {
+            file: "logic.ltx".into(),
+            section: "walker@1".to_owned(),
+            key: key.to_owned(),
+        };
+        let mut scenario = Scenario::new("entry", source("on_info"));
+        scenario.infos = vec!["a".to_owned()];
+        scenario.expect.output = Some("walker@2".to_owned());
+        assert!(scenario.run(&dir).is_empty());
+
+        let scenario = Scenario::new("missing", source("on_signal"));
+        assert_eq!(scenario.run(&dir).len(), 1);
+    }
+
+    #[test]
+    fn simulation() {
+        let dir = std::env::temp_dir().join("condlist-scenario-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("npc.ltx"),
+            "[logic]\nactive = walker@1\n[walker@1]\non_info = {+a} walker@2 %+b%\n[walker@2]\n",
+        )
+        .unwrap();
+
+        let mut scenario = Scenario::new("npc", Source::Logic("npc.ltx".into()));
+        scenario.ticks = 3;
+        scenario
+            .timeline
+            .at(2, crate::sim::Event::Give("a".to_owned()));
+        scenario.expect.section = Some("walker@2".to_owned());
+        scenario.expect.infos = Some(vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(scenario.run(&dir), Vec::<String>::new());
+
+        scenario.ticks = 1;
+        assert_eq!(
+            scenario.run(&dir),
+            vec![
+                "section: expected walker@2, got walker@1",
+                "infos: expected [a, b], got []",
+            ]
+        );
+    }
+
+    #[cfg(feature = "scenario")]
+    #[test]
+    fn toml() {
+        let scenarios = from_toml(
+            r#"
+            [[scenario]]
+            name = "inline"
+            condlist = "{=f} a, b"
+            stubs = { f = true, "g(1)" = false }
+            seed = 2
+
+            [scenario.expect]
+            output = "a"
+            calls = []
+
+            [[scenario]]
+            name = "simulated"
+            ltx = "a.ltx"
+            ticks = 4
+            events = { 2 = ["+x", "f(1)=true"] }
+            expect = { section = "nil" }
+
+            [[scenario]]
+            name = "entry"
+            ltx = "a.ltx"
+            section = "s"
+            key = "k"
+            infos = ["x"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenarios.len(), 3);
+        assert_eq!(scenarios[0].stubs, vec!["f=true", "g(1)=false"]);
+        assert_eq!(scenarios[0].seed, 2);
+        assert_eq!(scenarios[0].expect.calls, Some(vec![]));
+        assert_eq!(scenarios[0].run(Path::new(".")), Vec::<String>::new());
+        assert_eq!(scenarios[1].ticks, 4);
+        assert_eq!(
+            scenarios[1]
+                .timeline
+                .events(2)
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+            vec!["+x", "f(1)=true"]
+        );
+        assert_eq!(
+            scenarios[2].source,
+            Source::Entry {
+                file: "a.ltx".into(),
+                section: "s".to_owned(),
+                key: "k".to_owned()
+            }
+        );
+
+        let error = |text| from_toml(text).unwrap_err();
+        assert_eq!(
+            error("[[scenario]]\nname = \"x\"\ncondlist = \"a\"\nexpected = {}"),
+            "scenario 1: unknown key `expected`"
+        );
+        assert_eq!(
+            error("[[scenario]]\nname = \"x\"\nltx = \"a\"\nkey = \"k\""),
+            "scenario 1: expected `condlist`, `ltx` or `ltx`, `section` and `key`"
+        );
+        assert_eq!(
+            error("[[scenario]]\nname = \"x\"\ncondlist = \"a\"\nticks = 3"),
+            "scenario 1: `ticks` is only for simulations"
+        );
+    }
+}
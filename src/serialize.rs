@@ -0,0 +1,181 @@
+//! `serde` support. The schema is the one written by [`crate::json`]: every
+//! `Slice` inside an `Ast` is serialized as `{"index", "len", "text"}`.
+
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, SerializeStruct, Serializer};
+
+use crate::lint::{Diagnostic, Severity};
+use crate::parser::{Ast, Block, Slice, Statement};
+
+/// A node together with the `Ast` its slices point into.
+struct Resolved<'s, 'a, T: ?Sized>(&'s Ast<'a>, &'s T);
+
+impl Serialize for Slice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_struct("Slice", 2)?;
+        out.serialize_field("index", &self.index())?;
+        out.serialize_field("len", &self.len())?;
+        out.end()
+    }
+}
+
+impl Serialize for Resolved<'_, '_, Slice> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_struct("Slice", 3)?;
+        out.serialize_field("index", &self.1.index())?;
+        out.serialize_field("len", &self.1.len())?;
+        out.serialize_field("text", self.0.slice_as_str(self.1))?;
+        out.end()
+    }
+}
+
+impl Serialize for Resolved<'_, '_, [Slice]> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_seq(Some(self.1.len()))?;
+        for x in self.1 {
+            out.serialize_element(&Resolved(self.0, x))?;
+        }
+        out.end()
+    }
+}
+
+impl Serialize for Resolved<'_, '_, Block> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_map(None)?;
+        match self.1 {
+            Block::InfoPortion { key, inverted } => {
+                out.serialize_entry("kind", "info")?;
+                out.serialize_entry("key", &Resolved(self.0, key))?;
+                out.serialize_entry("inverted", inverted)?;
+            }
+            Block::Call {
+                function,
+                args,
+                inverted,
+            } => {
+                out.serialize_entry("kind", "call")?;
+                out.serialize_entry("function", &Resolved(self.0, function))?;
+                out.serialize_entry("args", &Resolved(self.0, args.as_slice()))?;
+                out.serialize_entry("inverted", inverted)?;
+            }
+            Block::Chance { val } => {
+                out.serialize_entry("kind", "chance")?;
+                out.serialize_entry("value", &Resolved(self.0, val))?;
+            }
+        }
+        out.end()
+    }
+}
+
+impl Serialize for Resolved<'_, '_, [Block]> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_seq(Some(self.1.len()))?;
+        for x in self.1 {
+            out.serialize_element(&Resolved(self.0, x))?;
+        }
+        out.end()
+    }
+}
+
+impl Serialize for Resolved<'_, '_, Statement> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (ast, statement) = (self.0, self.1);
+        let mut out = serializer.serialize_struct("Statement", 3)?;
+        out.serialize_field(
+            "condition",
+            &statement.conditions().map(|x| Resolved(ast, x.blocks())),
+        )?;
+        out.serialize_field("output", &statement.val().map(|x| Resolved(ast, x)))?;
+        out.serialize_field(
+            "effects",
+            &statement.effects().map(|x| Resolved(ast, x.blocks())),
+        )?;
+        out.end()
+    }
+}
+
+impl Serialize for Ast<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_struct("Ast", 1)?;
+        out.serialize_field(
+            "statements",
+            &self
+                .statements()
+                .iter()
+                .map(|x| Resolved(self, x))
+                .collect::<Vec<_>>(),
+        )?;
+        out.end()
+    }
+}
+
+impl Serialize for Severity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+impl Serialize for Diagnostic {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut out = serializer.serialize_struct("Diagnostic", 4)?;
+        out.serialize_field("span", &self.span)?;
+        out.serialize_field("severity", &self.severity)?;
+        out.serialize_field("code", self.code)?;
+        out.serialize_field("message", &self.message)?;
+        out.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+
+    #[test]
+    fn same_schema_as_json() {
+        for src in [
+            "Y",
+            "{} X, Y",
+            "{=A(a1:a2) !B +C -D ~30} X %=E(e1) +F -G%, Y",
+            "{=f()} %+a%",
+        ] {
+            let ast = Ast::from(src).unwrap();
+            assert_eq!(serde_json::to_string(&ast).unwrap(), json::ast(&ast));
+        }
+    }
+
+    #[test]
+    fn golden() {
+        let ast = Ast::from("{!is_alive(wolf)} walker@2 %+done%").unwrap();
+        assert_eq!(
+            serde_json::to_value(&ast).unwrap(),
+            serde_json::json!({
+                "statements": [{
+                    "condition": [{
+                        "kind": "call",
+                        "function": {"index": 2, "len": 8, "text": "is_alive"},
+                        "args": [{"index": 11, "len": 4, "text": "wolf"}],
+                        "inverted": true,
+                    }],
+                    "output": {"index": 18, "len": 8, "text": "walker@2"},
+                    "effects": [{
+                        "kind": "info",
+                        "key": {"index": 29, "len": 4, "text": "done"},
+                        "inverted": false,
+                    }],
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn diagnostic() {
+        let diagnostic = Diagnostic::warning(Slice::new(3, 2), "code", "message".to_owned());
+        assert_eq!(
+            serde_json::to_string(&diagnostic).unwrap(),
+            "{\"span\":{\"index\":3,\"len\":2},\"severity\":\"warning\",\"code\":\"code\",\"message\":\"message\"}"
+        );
+    }
+}
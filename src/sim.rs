@@ -0,0 +1,320 @@
+//! Runs the logic of one NPC over time. Tick 0 evaluates `active` of
+//! `[logic]`; every later tick evaluates the `on_info`, `on_info2`, ...
+//! condlists of the active section in the engine's order, running their
+//! effects, until one switches to another section. Like `xr_logic`, the new
+//! section is only checked on the next tick, and `nil` ends the logic.
+//!
+//! The world changes between ticks as a [`Timeline`] says, standing in for
+//! the player and the rest of the game.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::eval::{State, World};
+use crate::ltx::Ltx;
+use crate::parser::Slice;
+use crate::trace::{Step, trace};
+
+/// A change of the world made outside the logic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Give(String),
+    Disable(String),
+    /// A new condition result, as accepted by [`State::stub_str`].
+    Stub(String),
+}
+
+impl Event {
+    /// `+info`, `-info` or `F(A:B)=BOOL`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        if let Some(x) = text.strip_prefix('+') {
+            Ok(Event::Give(x.to_owned()))
+        } else if let Some(x) = text.strip_prefix('-') {
+            Ok(Event::Disable(x.to_owned()))
+        } else {
+            // Checked now rather than in the middle of a run.
+            State::default().stub_str(text)?;
+            Ok(Event::Stub(text.to_owned()))
+        }
+    }
+
+    fn apply(&self, state: &mut State) -> Result<(), String> {
+        match self {
+            Event::Give(x) => state.give_info(x),
+            Event::Disable(x) => state.disable_info(x),
+            Event::Stub(x) => state.stub_str(x)?,
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Give(x) => write!(f, "+{}", x),
+            Event::Disable(x) => write!(f, "-{}", x),
+            Event::Stub(x) => f.write_str(x),
+        }
+    }
+}
+
+/// Events by the tick before which they happen.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Timeline {
+    events: BTreeMap<usize, Vec<Event>>,
+}
+
+impl Timeline {
+    pub fn at(&mut self, tick: usize, event: Event) {
+        self.events.entry(tick).or_default().push(event);
+    }
+
+    pub fn events(&self, tick: usize) -> &[Event] {
+        self.events.get(&tick).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+}
+
+/// A condlist evaluated during a tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    pub key: String,
+    /// The condlist in the file.
+    pub value: Slice,
+    pub step: Step,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tick {
+    pub tick: usize,
+    pub events: Vec<Event>,
+    /// The active section before and after the tick, `None` before the
+    /// start and after `nil`.
+    pub from: Option<String>,
+    pub section: Option<String>,
+    pub evaluations: Vec<Evaluation>,
+}
+
+/// `tick: from -> to (key)` when the section changes, `tick: section`
+/// otherwise, then events and the changes made by each condlist.
+impl fmt::Display for Tick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |x: &Option<String>| x.clone().unwrap_or("nil".to_owned());
+        if self.from == self.section && self.tick > 0 {
+            write!(f, "{}: {}", self.tick, name(&self.section))?;
+        } else {
+            let key = self.evaluations.last().map(|x| x.key.as_str());
+            write!(
+                f,
+                "{}: {} -> {} ({})",
+                self.tick,
+                name(&self.from),
+                name(&self.section),
+                key.unwrap_or_default()
+            )?;
+        }
+        if !self.events.is_empty() {
+            let events = self.events.iter().map(|x| x.to_string());
+            write!(f, "\n  events: {}", events.collect::<Vec<_>>().join(" "))?;
+        }
+        for x in self.evaluations.iter().filter(|x| !x.step.diff.is_empty()) {
+            write!(f, "\n  {}: {}", x.key, x.step.diff)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct Simulation<'l, 'a> {
+    ltx: &'l Ltx<'a>,
+    pub state: State,
+    pub section: Option<String>,
+    pub log: Vec<Tick>,
+}
+
+impl<'l, 'a> Simulation<'l, 'a> {
+    pub fn new(ltx: &'l Ltx<'a>, state: State) -> Self {
+        Self {
+            ltx,
+            state,
+            section: None,
+            log: Vec::new(),
+        }
+    }
+
+    /// Runs ticks until `ticks` ticks after the start.
+    pub fn run(&mut self, timeline: &Timeline, ticks: usize) -> Result<(), String> {
+        while self.log.len() <= ticks {
+            self.tick(timeline)?;
+        }
+        Ok(())
+    }
+
+    pub fn tick(&mut self, timeline: &Timeline) -> Result<&Tick, String> {
+        let tick = self.log.len();
+        if tick == 0 && self.ltx.condlist("logic", "active").is_none() {
+            return Err("no `active` in [logic]".to_owned());
+        }
+        let events = timeline.events(tick);
+        for event in events {
+            event.apply(&mut self.state)?;
+        }
+
+        let from = self.section.clone();
+        let mut evaluations = Vec::new();
+        let keys = match (tick, &from) {
+            (0, _) => vec![("logic".to_owned(), "active".to_owned())],
+            (_, Some(section)) => self.on_info_keys(section),
+            (_, None) => Vec::new(),
+        };
+        for (section, key) in keys {
+            let condlist = self.ltx.condlist(&section, &key).expect("listed above");
+            let ast = self.ltx.parse(&condlist)?;
+            let step = trace(&ast, &mut self.state)
+                .map_err(|e| format!("tick {}: [{}] {}: {}", tick, section, key, e))?;
+            let output = step.outcome.output.clone();
+            evaluations.push(Evaluation {
+                key,
+                value: condlist.value,
+                step,
+            });
+            match output.as_deref() {
+                None => {}
+                Some("nil") => {
+                    self.section = None;
+                    break;
+                }
+                Some(x) if Some(x) == from.as_deref() => {}
+                Some(x) if self.ltx.section(x).is_some() => {
+                    self.section = Some(x.to_owned());
+                    break;
+                }
+                Some(x) => {
+                    return Err(format!(
+                        "tick {}: [{}] switches to undefined section [{}]",
+                        tick, section, x
+                    ));
+                }
+            }
+        }
+
+        self.log.push(Tick {
+            tick,
+            events: events.to_vec(),
+            from,
+            section: self.section.clone(),
+            evaluations,
+        });
+        Ok(self.log.last().expect("just pushed"))
+    }
+
+    /// The keys matching `^on_info%d*$` in `section`, in the order
+    /// `xr_logic.cfg_get_switch_conditions` reads them by line number. The
+    /// engine keeps the entries of a section sorted by key, so that is
+    /// `on_info`, `on_info10`, `on_info2` whatever the order in the file.
+    fn on_info_keys(&self, section: &str) -> Vec<(String, String)> {
+        let mut keys = self
+            .ltx
+            .condlists()
+            .filter(|x| self.ltx.slice_as_str(x.section.name()) == section)
+            .map(|x| self.ltx.slice_as_str(x.entry.key()))
+            .filter(|x| {
+                x.strip_prefix("on_info")
+                    .is_some_and(|x| x.chars().all(|x| x.is_ascii_digit()))
+            })
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        keys.into_iter()
+            .map(|key| (section.to_owned(), key.to_owned()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIC: &str = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info2 = {+talked} walker@2 %+met%
+on_info = {=is_alive(wolf)} walker@1 %=play_sound(howl)%
+
+[walker@2]
+on_info = {+met} remark@3 %-met%
+
+[remark@3]
+on_info = {=see_actor} nil
+";
+
+    #[test]
+    fn progression() {
+        let ltx = Ltx::from(LOGIC).unwrap();
+        let mut state = State::default();
+        state.stub("is_alive", None, true);
+        state.stub("see_actor", None, false);
+        let mut timeline = Timeline::default();
+        timeline.at(2, Event::parse("+talked").unwrap());
+        timeline.at(5, Event::parse("see_actor=true").unwrap());
+
+        let mut sim = Simulation::new(&ltx, state);
+        sim.run(&timeline, 6).unwrap();
+        assert_eq!(sim.section, None);
+        assert!(!sim.state.has_info("met"));
+        assert_eq!(
+            sim.log
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            "\
+0: nil -> walker@1 (active)
+1: walker@1
+  on_info: =play_sound(howl)
+2: walker@1 -> walker@2 (on_info2)
+  events: +talked
+  on_info: =play_sound(howl)
+  on_info2: +met
+3: walker@2 -> remark@3 (on_info)
+  on_info: -met
+4: remark@3
+5: remark@3 -> nil (on_info)
+  events: see_actor=true
+6: nil"
+        );
+    }
+
+    #[test]
+    fn key_order() {
+        let ltx = Ltx::from(
+            "[logic]\nactive = a\n[a]\non_info2 = x\non_info10 = b\non_info = %+c%\n[b]\n",
+        )
+        .unwrap();
+        let mut sim = Simulation::new(&ltx, State::default());
+        sim.run(&Timeline::default(), 1).unwrap();
+        let keys = sim.log[1]
+            .evaluations
+            .iter()
+            .map(|x| x.key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["on_info", "on_info10"]);
+        assert_eq!(sim.section.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn errors() {
+        let ltx = Ltx::from("[walker@1]\non_info = x\n").unwrap();
+        let mut sim = Simulation::new(&ltx, State::default());
+        assert!(sim.tick(&Timeline::default()).is_err());
+
+        let ltx = Ltx::from("[logic]\nactive = a\n[a]\non_info = b\n").unwrap();
+        let mut sim = Simulation::new(&ltx, State::default());
+        assert_eq!(
+            sim.run(&Timeline::default(), 1),
+            Err("tick 1: [a] switches to undefined section [b]".to_owned())
+        );
+        assert!(Event::parse("f").is_err());
+    }
+}
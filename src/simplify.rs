@@ -0,0 +1,277 @@
+use crate::logic::{Conjunction, Literal, chance};
+use crate::order::impure;
+use crate::parser::{Ast, Block, OwnedAst, Statement};
+
+/// A statement as the simplifier sees it.
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    condition: Conjunction,
+    out: Option<String>,
+    effects: Vec<String>,
+    /// For statements calling conditions with side effects: the blocks up to
+    /// the last such call, kept as written, and the condition of the rest.
+    impure: Option<(Vec<String>, Conjunction)>,
+}
+
+impl Rule {
+    fn from_statement(ast: &Ast, statement: &Statement) -> Self {
+        let blocks = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+        let impure = blocks.iter().rposition(|x| impure(ast, x)).map(|last| {
+            let rest = Conjunction::from_blocks(ast, &blocks[last + 1..]);
+            // A rest that never holds is kept too, it still runs the calls.
+            let (fixed, rest) = if rest.contradictory {
+                (blocks, Conjunction::default())
+            } else {
+                (&blocks[..=last], rest)
+            };
+            (
+                fixed.iter().filter_map(|x| block_text(ast, x)).collect(),
+                rest,
+            )
+        });
+        Self {
+            condition: Conjunction::from_blocks(ast, blocks),
+            out: statement.val().map(|v| ast.slice_as_str(v).to_owned()),
+            effects: statement
+                .effects()
+                .map(|e| e.blocks())
+                .unwrap_or(&[])
+                .iter()
+                .map(|b| ast.format_block(b))
+                .collect(),
+            impure,
+        }
+    }
+
+    fn same_behaviour(&self, other: &Self) -> bool {
+        self.out == other.out && self.effects == other.effects
+    }
+
+    fn text(&self) -> String {
+        let mut parts = Vec::new();
+        let blocks = match &self.impure {
+            Some((fixed, rest)) => {
+                let mut blocks = fixed.clone();
+                for x in canonical(rest) {
+                    if !blocks.contains(&x) {
+                        blocks.push(x);
+                    }
+                }
+                blocks
+            }
+            None => canonical(&self.condition),
+        };
+        if !blocks.is_empty() {
+            parts.push(format!("{{{}}}", blocks.join(" ")));
+        }
+        parts.extend(self.out.clone());
+        if !self.effects.is_empty() {
+            parts.push(format!("%{}%", self.effects.join(" ")));
+        }
+        parts.join(" ")
+    }
+}
+
+/// The blocks of a condition: info portions, calls, then the chance.
+fn canonical(condition: &Conjunction) -> Vec<String> {
+    let mut blocks = condition
+        .literals
+        .iter()
+        .map(|(atom, value)| {
+            Literal {
+                atom: atom.clone(),
+                value: *value,
+            }
+            .to_string()
+        })
+        .collect::<Vec<_>>();
+    blocks.extend(condition.chance.map(|x| format!("~{}", x)));
+    blocks
+}
+
+fn block_text(ast: &Ast, block: &Block) -> Option<String> {
+    match chance(ast, block) {
+        Some(x) => Some(format!("~{}", x)),
+        None => Literal::from_block(ast, block).map(|x| x.to_string()),
+    }
+}
+
+/// Rewrites a condlist into a shorter one with the same behaviour, as decided
+/// by [`crate::equivalence::equivalent`]:
+///
+/// - duplicate condition blocks and chances implied by a lower one are removed,
+/// - statements that can never be chosen are dropped,
+/// - a statement is dropped when the next one has the same output and effects
+///   and holds whenever it does,
+/// - adjacent statements with the same output and effects whose conditions
+///   differ only in one negated block are merged,
+/// - condition blocks are sorted: info portions, calls, then the chance.
+///
+/// Effects are kept as written, their order matters. So are conditions with
+/// side effects and the blocks before them: statements checking such a
+/// condition are never dropped or merged, and whether it runs must not change.
+pub fn simplify(ast: &Ast) -> Result<OwnedAst, String> {
+    let mut rules = ast
+        .statements()
+        .iter()
+        .map(|x| Rule::from_statement(ast, x))
+        .collect::<Vec<_>>();
+
+    while drop_unreachable(&mut rules) || drop_redundant(&mut rules) || merge_adjacent(&mut rules) {
+    }
+
+    let text = rules.iter().map(|x| x.text()).collect::<Vec<_>>();
+    Ast::from_string(text.join(", "))
+}
+
+/// Drops statements implied by an earlier one, or never holding, and those
+/// never checked.
+fn drop_unreachable(rules: &mut Vec<Rule>) -> bool {
+    let len = rules.len();
+    let mut kept: Vec<Rule> = Vec::with_capacity(len);
+    for rule in rules.drain(..) {
+        // Calls with side effects run unless an earlier statement always holds.
+        let shadowed = kept.iter().any(|x| x.condition.is_always())
+            || rule.impure.is_none()
+                && (rule.condition.contradictory
+                    || kept.iter().any(|x| rule.condition.implies(&x.condition)));
+        if !shadowed {
+            kept.push(rule);
+        }
+    }
+    *rules = kept;
+    rules.len() != len
+}
+
+/// Drops a statement when the next one behaves the same and holds whenever it
+/// does: without it, the next one is chosen instead. A last statement without
+/// output and effects does nothing either.
+fn drop_redundant(rules: &mut Vec<Rule>) -> bool {
+    if rules
+        .last()
+        .is_some_and(|x| x.out.is_none() && x.effects.is_empty() && x.impure.is_none())
+    {
+        rules.pop();
+        return true;
+    }
+    for i in 0..rules.len().saturating_sub(1) {
+        let (a, b) = (&rules[i], &rules[i + 1]);
+        if a.same_behaviour(b)
+            && a.condition.implies(&b.condition)
+            && a.impure.is_none()
+            && b.impure.is_none()
+        {
+            rules.remove(i);
+            return true;
+        }
+    }
+    false
+}
+
+/// Merges `{C +x} X, {C -x} X` into `{C} X`.
+fn merge_adjacent(rules: &mut Vec<Rule>) -> bool {
+    for i in 0..rules.len().saturating_sub(1) {
+        let (a, b) = (&rules[i].condition, &rules[i + 1].condition);
+        if !rules[i].same_behaviour(&rules[i + 1])
+            || rules[i].impure.is_some()
+            || rules[i + 1].impure.is_some()
+            || a.chance != b.chance
+            || a.literals.len() != b.literals.len()
+        {
+            continue;
+        }
+        let differing = a
+            .literals
+            .iter()
+            .filter(|(atom, value)| b.literals.get(*atom) != Some(*value))
+            .collect::<Vec<_>>();
+        let [(atom, value)] = differing.as_slice() else {
+            continue;
+        };
+        if b.literals.get(*atom) != Some(&!**value) {
+            continue;
+        }
+        let atom = (*atom).clone();
+        rules[i].condition.literals.remove(&atom);
+        rules.remove(i + 1);
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::equivalence::equivalent;
+
+    fn simplified(src: &str) -> String {
+        let ast = Ast::from(src).unwrap();
+        let out = simplify(&ast).unwrap();
+        assert_eq!(equivalent(&ast, &out).unwrap(), None, "{} -> {}", src, out);
+        out.to_string()
+    }
+
+    #[test]
+    fn duplicate_blocks() {
+        assert_eq!(simplified("{+a +a ~50 ~30} X, Y"), "{+a ~30} X, Y");
+        assert_eq!(simplified("{~100 +a} X"), "{+a} X");
+    }
+
+    #[test]
+    fn unreachable() {
+        assert_eq!(
+            simplified("{+a} X, {+a +b} Y, {+b -b} Z, W, V"),
+            "{+a} X, W"
+        );
+    }
+
+    #[test]
+    fn redundant() {
+        assert_eq!(
+            simplified("{+a +b} X %+c%, {+a} X %+c%, Y"),
+            "{+a} X %+c%, Y"
+        );
+        assert_eq!(simplified("{+a} X, {+b} Y, {+b} Y"), "{+a} X, {+b} Y");
+        assert_eq!(simplified("{+a} X, {+b}, {-a}"), "{+a} X");
+        // Different effects, both are needed.
+        assert_eq!(
+            simplified("{+a +b} X %+c%, {+a} X, Y"),
+            "{+a +b} X %+c%, {+a} X, Y"
+        );
+    }
+
+    #[test]
+    fn merge() {
+        assert_eq!(simplified("{+a =f} X, {+a !f} X, Y"), "{+a} X, Y");
+        assert_eq!(simplified("{-b} X, {+b} X, Y"), "X");
+        // Not adjacent: `{+b} Y` decides in between.
+        assert_eq!(
+            simplified("{+a} X, {+b} Y, {-a} X"),
+            "{+a} X, {+b} Y, {-a} X"
+        );
+    }
+
+    #[test]
+    fn canonical_order() {
+        assert_eq!(simplified("{~10 =f(x) -b +a} X"), "{+a -b =f(x) ~10} X");
+    }
+
+    #[test]
+    fn invalid_chance() {
+        assert_eq!(simplified("{~ +a} X, Y"), "{+a ~} X, Y");
+    }
+
+    #[test]
+    fn side_effects() {
+        assert_eq!(
+            simplified("{+b =random_choice(50) ~10 +a +b} X, {+c} X, Y"),
+            "{+b =random_choice(50) +a ~10} X, {+c} X, Y"
+        );
+        // Never chosen, but the call still runs.
+        assert_eq!(
+            simplified("{+a} X, {=random_choice(50) -a +a} X, Y"),
+            "{+a} X, {=random_choice(50) -a +a} X, Y"
+        );
+        assert_eq!(simplified("X, {=random_choice(50)} Y"), "X");
+    }
+}
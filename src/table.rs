@@ -0,0 +1,225 @@
+//! Decision tables: what a condlist chooses for every assignment of the atoms
+//! of its conditions. Assignments giving the same result are merged, leaving
+//! atoms that do not matter blank.
+
+use std::collections::BTreeMap;
+
+use crate::equivalence::{Behaviour, Model, atoms, roll_ranges};
+use crate::logic::Atom;
+use crate::parser::Ast;
+
+/// One group of assignments. `values` follows the atoms of the table, `None`
+/// when the atom does not matter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub values: Vec<Option<bool>>,
+    /// Every possible behaviour with its probability in percent.
+    pub outcomes: Vec<(Behaviour, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecisionTable {
+    pub atoms: Vec<Atom>,
+    pub rows: Vec<Row>,
+}
+
+impl DecisionTable {
+    pub fn from_ast(ast: &Ast) -> Result<Self, String> {
+        let model = Model::new(ast);
+        let conditions = || model.statements.iter().map(|(c, _)| c);
+        let atoms = atoms(conditions())?;
+        let ranges = roll_ranges(conditions());
+
+        // The first atom varies slowest, true before false.
+        let mut rows = (0..1u32 << atoms.len())
+            .map(|bits| {
+                let values = (0..atoms.len())
+                    .map(|i| bits & (1 << (atoms.len() - 1 - i)) == 0)
+                    .collect::<Vec<_>>();
+                let world = atoms.iter().cloned().zip(values.iter().copied());
+                Row {
+                    outcomes: model.distribution(&world.collect::<BTreeMap<_, _>>(), &ranges),
+                    values: values.into_iter().map(Some).collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        while merge(&mut rows) {}
+
+        Ok(Self { atoms, rows })
+    }
+
+    /// Whether some row depends on the roll of a `~N` check.
+    fn has_chance(&self) -> bool {
+        self.rows.iter().any(|x| x.outcomes.len() > 1)
+    }
+
+    /// Header and cells of every line, one line per outcome.
+    fn cells(&self) -> (Vec<String>, Vec<Vec<String>>) {
+        let chance = self.has_chance();
+        let mut header = self.atoms.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        if chance {
+            header.push("chance".to_owned());
+        }
+        header.extend(["output".to_owned(), "effects".to_owned()]);
+
+        let mut lines = Vec::new();
+        for row in &self.rows {
+            for (behaviour, percent) in &row.outcomes {
+                let mut line = row
+                    .values
+                    .iter()
+                    .map(|x| match x {
+                        Some(true) => "Y",
+                        Some(false) => "N",
+                        None => "-",
+                    })
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>();
+                if chance {
+                    line.push(format!("{}%", percent));
+                }
+                line.push(behaviour.output.as_deref().unwrap_or("nil").to_owned());
+                line.push(behaviour.effects.join(" "));
+                lines.push(line);
+            }
+        }
+        (header, lines)
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let (header, lines) = self.cells();
+        let escape = |x: &String| x.replace('|', "\\|");
+        let mut out = String::new();
+        let mut line = |cells: Vec<String>| {
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        };
+        line(header.iter().map(escape).collect());
+        line(header.iter().map(|_| "---".to_owned()).collect());
+        for x in lines {
+            line(x.iter().map(escape).collect());
+        }
+        out
+    }
+
+    /// RFC 4180, with CRLF line endings.
+    pub fn to_csv(&self) -> String {
+        let (header, lines) = self.cells();
+        let escape = |x: &String| {
+            if x.contains([',', '"', '\r', '\n']) {
+                format!("\"{}\"", x.replace('"', "\"\""))
+            } else {
+                x.clone()
+            }
+        };
+        std::iter::once(header)
+            .chain(lines)
+            .map(|x| x.iter().map(escape).collect::<Vec<_>>().join(",") + "\r\n")
+            .collect()
+    }
+
+    /// A standalone `<table>` element.
+    pub fn to_html(&self) -> String {
+        let (header, lines) = self.cells();
+        let escape = |x: &String| {
+            x.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        };
+        let row = |tag: &str, cells: &[String]| {
+            let cells = cells
+                .iter()
+                .map(|x| format!("<{tag}>{}</{tag}>", escape(x)))
+                .collect::<String>();
+            format!("<tr>{}</tr>\n", cells)
+        };
+        let mut out = String::from("<table>\n<thead>\n");
+        out.push_str(&row("th", &header));
+        out.push_str("</thead>\n<tbody>\n");
+        for x in &lines {
+            out.push_str(&row("td", x));
+        }
+        out.push_str("</tbody>\n</table>\n");
+        out
+    }
+}
+
+/// Merges two rows with the same outcomes differing in a single atom, making
+/// it irrelevant. Returns whether a merge happened.
+fn merge(rows: &mut Vec<Row>) -> bool {
+    for i in 0..rows.len() {
+        for j in i + 1..rows.len() {
+            let (a, b) = (&rows[i], &rows[j]);
+            if a.outcomes != b.outcomes {
+                continue;
+            }
+            let differing = a
+                .values
+                .iter()
+                .zip(&b.values)
+                .enumerate()
+                .filter(|(_, (x, y))| x != y)
+                .collect::<Vec<_>>();
+            let [(ix, (Some(_), Some(_)))] = differing.as_slice() else {
+                continue;
+            };
+            let ix = *ix;
+            rows[i].values[ix] = None;
+            rows.remove(j);
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(src: &str) -> DecisionTable {
+        DecisionTable::from_ast(&Ast::from(src).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn merges_irrelevant_atoms() {
+        let x = table("{+a} X %+c%, {=f(1)} Y, Z");
+        assert_eq!(
+            x.to_markdown(),
+            "\
+| +a | =f(1) | output | effects |
+| --- | --- | --- | --- |
+| Y | - | X | +c |
+| N | Y | Y |  |
+| N | N | Z |  |
+"
+        );
+    }
+
+    #[test]
+    fn chances() {
+        let x = table("{+a ~30} X, Y");
+        assert_eq!(
+            x.to_csv(),
+            "+a,chance,output,effects\r\nY,30%,X,\r\nY,70%,Y,\r\nN,100%,Y,\r\n"
+        );
+    }
+
+    #[test]
+    fn html() {
+        let x = table("{!f(a:b)} X");
+        assert_eq!(
+            x.to_html(),
+            "\
+<table>
+<thead>
+<tr><th>=f(a:b)</th><th>output</th><th>effects</th></tr>
+</thead>
+<tbody>
+<tr><td>Y</td><td>nil</td><td></td></tr>
+<tr><td>N</td><td>X</td><td></td></tr>
+</tbody>
+</table>
+"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+//! What an evaluation did to the world: every effect in the order it ran, and
+//! the difference between the states before and after. The two differ when
+//! effects cancel out or change nothing, e.g. `%-a%` without `a`.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::eval::{Outcome, State, World, evaluate_with};
+use crate::parser::Ast;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Give(String),
+    Disable(String),
+    /// `xr_effects.<function>(args)`.
+    Call(String, Vec<String>),
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Give(x) => write!(f, "+{}", x),
+            Action::Disable(x) => write!(f, "-{}", x),
+            Action::Call(function, args) if args.is_empty() => write!(f, "={}", function),
+            Action::Call(function, args) => write!(f, "={}({})", function, args.join(":")),
+        }
+    }
+}
+
+/// A [`World`] recording the effects run through it.
+pub struct Recorder<'w, W> {
+    world: &'w mut W,
+    pub actions: Vec<Action>,
+}
+
+impl<'w, W: World> Recorder<'w, W> {
+    pub fn new(world: &'w mut W) -> Self {
+        Self {
+            world,
+            actions: Vec::new(),
+        }
+    }
+}
+
+impl<W: World> World for Recorder<'_, W> {
+    fn has_info(&self, key: &str) -> bool {
+        self.world.has_info(key)
+    }
+
+    fn give_info(&mut self, key: &str) {
+        self.actions.push(Action::Give(key.to_owned()));
+        self.world.give_info(key)
+    }
+
+    fn disable_info(&mut self, key: &str) {
+        self.actions.push(Action::Disable(key.to_owned()));
+        self.world.disable_info(key)
+    }
+
+    fn condition(&mut self, function: &str, args: &[&str]) -> Result<bool, String> {
+        self.world.condition(function, args)
+    }
+
+    fn effect(&mut self, function: &str, args: &[&str]) -> Result<(), String> {
+        self.actions.push(Action::Call(
+            function.to_owned(),
+            args.iter().map(|x| x.to_string()).collect(),
+        ));
+        self.world.effect(function, args)
+    }
+
+    fn roll(&mut self) -> u32 {
+        self.world.roll()
+    }
+}
+
+/// How a [`State`] changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub given: BTreeSet<String>,
+    pub disabled: BTreeSet<String>,
+    /// Effect calls made in between.
+    pub calls: Vec<(String, Vec<String>)>,
+}
+
+impl Diff {
+    pub fn new(before: &State, after: &State) -> Self {
+        Self {
+            given: after.infos.difference(&before.infos).cloned().collect(),
+            disabled: before.infos.difference(&after.infos).cloned().collect(),
+            calls: after
+                .calls
+                .get(before.calls.len()..)
+                .unwrap_or_default()
+                .to_vec(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.given.is_empty() && self.disabled.is_empty() && self.calls.is_empty()
+    }
+}
+
+/// `+given -disabled =call(args)`, calls in order.
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let given = self.given.iter().map(|x| Action::Give(x.clone()));
+        let disabled = self.disabled.iter().map(|x| Action::Disable(x.clone()));
+        let calls = self
+            .calls
+            .iter()
+            .map(|(function, args)| Action::Call(function.clone(), args.clone()));
+        let parts = given
+            .chain(disabled)
+            .chain(calls)
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        f.write_str(&parts.join(" "))
+    }
+}
+
+/// One traced evaluation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub outcome: Outcome,
+    /// Statement index, block index and result of every condition block
+    /// checked, in order.
+    pub checked: Vec<(usize, usize, bool)>,
+    pub actions: Vec<Action>,
+    pub diff: Diff,
+}
+
+/// [`evaluate`], recording what the effects did to `state`.
+pub fn trace(ast: &Ast, state: &mut State) -> Result<Step, String> {
+    let before = state.clone();
+    let mut recorder = Recorder::new(state);
+    let mut checked = Vec::new();
+    let outcome = evaluate_with(ast, &mut recorder, |i, j, value| {
+        checked.push((i, j, value))
+    })?;
+    let actions = recorder.actions;
+    Ok(Step {
+        outcome,
+        checked,
+        actions,
+        diff: Diff::new(&before, state),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_and_diff() {
+        let ast = Ast::from("{+a} x %-a +b -c +b =f(1:2) =g%").unwrap();
+        let mut state = State::default();
+        state.infos.insert("a".to_owned());
+
+        let step = trace(&ast, &mut state).unwrap();
+        assert_eq!(step.outcome.output.as_deref(), Some("x"));
+        assert_eq!(step.checked, vec![(0, 0, true)]);
+        assert_eq!(
+            step.actions
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+            vec!["-a", "+b", "-c", "+b", "=f(1:2)", "=g"]
+        );
+        assert_eq!(step.diff.to_string(), "+b -a =f(1:2) =g");
+
+        // Only the calls of this evaluation are part of its diff.
+        let step = trace(&Ast::from("{+b} y %+b =h%").unwrap(), &mut state).unwrap();
+        assert_eq!(step.diff.to_string(), "=h");
+        let step = trace(&Ast::from("z").unwrap(), &mut state).unwrap();
+        assert!(step.actions.is_empty() && step.diff.is_empty());
+    }
+}
@@ -0,0 +1,200 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Extensions of gamedata files that can contain condlists or info portions.
+const EXTENSIONS: &[&str] = &["ltx", "xml"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// Anything that is not valid UTF-8, usually cp1251. Every byte is kept as
+    /// one char so the file can be written back unchanged.
+    Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub text: String,
+    pub encoding: Encoding,
+}
+
+impl SourceFile {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let (text, encoding) = match String::from_utf8(bytes) {
+            Ok(x) => (x, Encoding::Utf8),
+            Err(e) => (
+                e.into_bytes().into_iter().map(char::from).collect(),
+                Encoding::Bytes,
+            ),
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            text,
+            encoding,
+        })
+    }
+
+    /// Writes `text` back to `path` in the original encoding.
+    pub fn write(&self) -> Result<(), String> {
+        let bytes = match self.encoding {
+            Encoding::Utf8 => self.text.as_bytes().to_owned(),
+            Encoding::Bytes => self
+                .text
+                .chars()
+                .map(|x| u8::try_from(x).map_err(|_| format!("`{}` does not fit in a byte", x)))
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+        fs::write(&self.path, bytes).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+
+    /// 1-based line and column of a byte offset into `text`.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map(|x| x + 1).unwrap_or(0) + 1;
+        (line, column)
+    }
+
+    pub fn is_ltx(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("ltx"))
+    }
+
+    pub fn is_xml(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|x| x.eq_ignore_ascii_case("xml"))
+    }
+}
+
+/// All LTX and XML files below a gamedata directory.
+#[derive(Debug, Default, PartialEq)]
+pub struct Tree {
+    files: Vec<SourceFile>,
+}
+
+impl Tree {
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        match Self::load_partial(dir) {
+            (tree, errors) if errors.is_empty() => Ok(tree),
+            (_, mut errors) => Err(errors.remove(0)),
+        }
+    }
+
+    /// [`Tree::load`] skipping the files and directories that cannot be read,
+    /// with their errors.
+    pub fn load_partial(dir: &Path) -> (Self, Vec<String>) {
+        let mut tree = Self::default();
+        let mut errors = Vec::new();
+        tree.walk(dir, &mut errors);
+        tree.files.sort_by(|a, b| a.path.cmp(&b.path));
+        (tree, errors)
+    }
+
+    fn walk(&mut self, dir: &Path, errors: &mut Vec<String>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(x) => x,
+            Err(e) => {
+                errors.push(format!("{}: {}", dir.display(), e));
+                return;
+            }
+        };
+        for entry in entries {
+            // Links are not followed into directories, they may loop.
+            let (path, is_dir) = match entry.and_then(|x| Ok((x.path(), x.file_type()?.is_dir()))) {
+                Ok(x) => x,
+                Err(e) => {
+                    errors.push(format!("{}: {}", dir.display(), e));
+                    continue;
+                }
+            };
+            if is_dir {
+                self.walk(&path, errors);
+            } else if path
+                .extension()
+                .and_then(|x| x.to_str())
+                .is_some_and(|x| EXTENSIONS.contains(&x.to_ascii_lowercase().as_str()))
+            {
+                match SourceFile::read(&path) {
+                    Ok(x) => self.files.push(x),
+                    Err(e) => errors.push(e),
+                }
+            }
+        }
+    }
+
+    pub fn push(&mut self, file: SourceFile) {
+        self.files.push(file);
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn file(&self, path: &Path) -> Option<&SourceFile> {
+        self.files.iter().find(|x| x.path == path)
+    }
+
+    pub fn file_mut(&mut self, path: &Path) -> Option<&mut SourceFile> {
+        self.files.iter_mut().find(|x| x.path == path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreadable_files() {
+        let dir = std::env::temp_dir().join("condlists-tree-unreadable");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.ltx"), "[s]\n").unwrap();
+        // A dangling link cannot be read.
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("b.ltx")).unwrap();
+
+        let (tree, errors) = Tree::load_partial(&dir);
+        assert_eq!(tree.files().len(), 1);
+        assert_eq!(errors.len(), usize::from(cfg!(unix)));
+        assert_eq!(Tree::load(&dir).is_err(), cfg!(unix));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loop() {
+        let dir = std::env::temp_dir().join("condlists-tree-loop");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/a.ltx"), "[s]\n").unwrap();
+        std::os::unix::fs::symlink(&dir, dir.join("sub/parent")).unwrap();
+
+        let tree = Tree::load(&dir).unwrap();
+        assert_eq!(tree.files().len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn non_utf8_roundtrip() {
+        let path = std::env::temp_dir().join("condlists-tree-roundtrip.ltx");
+        // cp1251 comment followed by a condlist.
+        let bytes = b"; \xcf\xf0\xe8\xe2\xe5\xf2\n[s]\non_info = {+a} b\n";
+        fs::write(&path, bytes).unwrap();
+
+        let mut file = SourceFile::read(&path).unwrap();
+        assert_eq!(file.encoding, Encoding::Bytes);
+        file.text = file.text.replace("{+a}", "{+c}");
+        file.write().unwrap();
+
+        let written = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            written,
+            b"; \xcf\xf0\xe8\xe2\xe5\xf2\n[s]\non_info = {+c} b\n"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use crate::parser::{Ast, Block, Slice, Statement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Condition,
+    Effect,
+}
+
+/// Where a block sits in its condlist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+    pub statement: usize,
+    pub position: Position,
+    /// Index of the block in its condition or effect, before any folding.
+    pub block: usize,
+}
+
+/// Read-only traversal. Nodes are visited in source order: conditions, output,
+/// effects.
+pub trait Visit {
+    fn visit_ast(&mut self, ast: &Ast) {
+        walk_ast(self, ast)
+    }
+
+    fn visit_statement(&mut self, ast: &Ast, statement: &Statement, ix: usize) {
+        walk_statement(self, ast, statement, ix)
+    }
+
+    fn visit_block(&mut self, _ast: &Ast, _block: &Block, _cx: Context) {}
+
+    fn visit_output(&mut self, _ast: &Ast, _out: &Slice, _statement: usize) {}
+}
+
+pub fn walk_ast<V: Visit + ?Sized>(visitor: &mut V, ast: &Ast) {
+    for (ix, statement) in ast.statements().iter().enumerate() {
+        visitor.visit_statement(ast, statement, ix);
+    }
+}
+
+pub fn walk_statement<V: Visit + ?Sized>(
+    visitor: &mut V,
+    ast: &Ast,
+    statement: &Statement,
+    ix: usize,
+) {
+    let conditions = statement.conditions().map(|x| x.blocks()).unwrap_or(&[]);
+    for (block, x) in conditions.iter().enumerate() {
+        let cx = Context {
+            statement: ix,
+            position: Position::Condition,
+            block,
+        };
+        visitor.visit_block(ast, x, cx);
+    }
+    if let Some(out) = statement.val() {
+        visitor.visit_output(ast, out, ix);
+    }
+    let effects = statement.effects().map(|x| x.blocks()).unwrap_or(&[]);
+    for (block, x) in effects.iter().enumerate() {
+        let cx = Context {
+            statement: ix,
+            position: Position::Effect,
+            block,
+        };
+        visitor.visit_block(ast, x, cx);
+    }
+}
+
+/// Traversal changing nodes in place. Slices still point into `src`, so a
+/// visitor can invert blocks or point them at other parts of the source.
+pub trait VisitMut {
+    fn visit_ast_mut(&mut self, ast: &mut Ast) {
+        walk_ast_mut(self, ast)
+    }
+
+    fn visit_statement_mut(&mut self, src: &str, statement: &mut Statement, ix: usize) {
+        walk_statement_mut(self, src, statement, ix)
+    }
+
+    fn visit_block_mut(&mut self, _src: &str, _block: &mut Block, _cx: Context) {}
+
+    fn visit_output_mut(&mut self, _src: &str, _out: &mut Option<Slice>, _statement: usize) {}
+}
+
+pub fn walk_ast_mut<V: VisitMut + ?Sized>(visitor: &mut V, ast: &mut Ast) {
+    let (src, statements) = ast.parts_mut();
+    for (ix, statement) in statements.iter_mut().enumerate() {
+        visitor.visit_statement_mut(src, statement, ix);
+    }
+}
+
+pub fn walk_statement_mut<V: VisitMut + ?Sized>(
+    visitor: &mut V,
+    src: &str,
+    statement: &mut Statement,
+    ix: usize,
+) {
+    let (conditions, effects, out) = statement.parts_mut();
+    for (block, x) in conditions.into_iter().flatten().enumerate() {
+        let cx = Context {
+            statement: ix,
+            position: Position::Condition,
+            block,
+        };
+        visitor.visit_block_mut(src, x, cx);
+    }
+    visitor.visit_output_mut(src, out, ix);
+    for (block, x) in effects.into_iter().flatten().enumerate() {
+        let cx = Context {
+            statement: ix,
+            position: Position::Effect,
+            block,
+        };
+        visitor.visit_block_mut(src, x, cx);
+    }
+}
+
+/// Traversal rebuilding the condlist, dropping or replacing nodes.
+pub trait Fold {
+    fn fold_statement(&mut self, src: &str, statement: Statement, ix: usize) -> Option<Statement> {
+        Some(walk_fold_statement(self, src, statement, ix))
+    }
+
+    fn fold_block(&mut self, _src: &str, block: Block, _cx: Context) -> Option<Block> {
+        Some(block)
+    }
+}
+
+pub fn fold<'a, F: Fold + ?Sized>(folder: &mut F, mut ast: Ast<'a>) -> Ast<'a> {
+    let (src, statements) = ast.parts_mut();
+    *statements = std::mem::take(statements)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(ix, x)| folder.fold_statement(src, x, ix))
+        .collect();
+    ast
+}
+
+pub fn walk_fold_statement<F: Fold + ?Sized>(
+    folder: &mut F,
+    src: &str,
+    mut statement: Statement,
+    ix: usize,
+) -> Statement {
+    let (conditions, effects, _) = statement.parts_mut();
+    for (blocks, position) in [
+        (conditions, Position::Condition),
+        (effects, Position::Effect),
+    ] {
+        let Some(blocks) = blocks else {
+            continue;
+        };
+        *blocks = std::mem::take(blocks)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(block, x)| {
+                let cx = Context {
+                    statement: ix,
+                    position,
+                    block,
+                };
+                folder.fold_block(src, x, cx)
+            })
+            .collect();
+    }
+    statement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Collect(Vec<(String, Option<Context>)>);
+
+    impl Visit for Collect {
+        fn visit_block(&mut self, ast: &Ast, block: &Block, cx: Context) {
+            self.0.push((ast.format_block(block), Some(cx)));
+        }
+
+        fn visit_output(&mut self, ast: &Ast, out: &Slice, _: usize) {
+            self.0.push((ast.slice_as_str(out).to_owned(), None));
+        }
+    }
+
+    #[test]
+    fn visit_order() {
+        let ast = Ast::from("{+a =f} X %-b%, Y").unwrap();
+        let mut collect = Collect::default();
+        collect.visit_ast(&ast);
+        let cx = |statement, position, block| {
+            Some(Context {
+                statement,
+                position,
+                block,
+            })
+        };
+        assert_eq!(
+            collect.0,
+            vec![
+                ("+a".to_owned(), cx(0, Position::Condition, 0)),
+                ("=f".to_owned(), cx(0, Position::Condition, 1)),
+                ("X".to_owned(), None),
+                ("-b".to_owned(), cx(0, Position::Effect, 0)),
+                ("Y".to_owned(), None),
+            ]
+        );
+    }
+
+    struct Negate;
+
+    impl VisitMut for Negate {
+        fn visit_block_mut(&mut self, _: &str, block: &mut Block, cx: Context) {
+            if cx.position != Position::Condition {
+                return;
+            }
+            match block {
+                Block::InfoPortion { inverted, .. } | Block::Call { inverted, .. } => {
+                    *inverted = !*inverted
+                }
+                Block::Chance { .. } => {}
+            }
+        }
+    }
+
+    #[test]
+    fn visit_mut() {
+        let mut ast = Ast::from("{+a !f ~5} X %+b%").unwrap();
+        Negate.visit_ast_mut(&mut ast);
+        assert_eq!(ast.to_string(), "{-a =f ~5} X %+b%");
+    }
+
+    /// Drops every block mentioning `b` and statements without output.
+    struct DropB;
+
+    impl Fold for DropB {
+        fn fold_statement(
+            &mut self,
+            src: &str,
+            statement: Statement,
+            ix: usize,
+        ) -> Option<Statement> {
+            statement.val()?;
+            Some(walk_fold_statement(self, src, statement, ix))
+        }
+
+        fn fold_block(&mut self, src: &str, block: Block, _: Context) -> Option<Block> {
+            match &block {
+                Block::InfoPortion { key, .. } if key.as_str(src) == "b" => None,
+                _ => Some(block),
+            }
+        }
+    }
+
+    #[test]
+    fn fold_drops() {
+        let ast = Ast::from("{+a -b} X %+b +c%, {+b} %+d%, Y").unwrap();
+        let ast = fold(&mut DropB, ast);
+        assert_eq!(ast.to_string(), "{+a} X %+c%, Y");
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use crate::lint::Diagnostic;
+use crate::ltx::Ltx;
+use crate::parser::{Ast, Block, Slice};
+use crate::tree::{SourceFile, Tree};
+use crate::visit::{Context, Position, Visit};
+
+/// XML tags of dialogs and tasks that take an info portion as their text.
+const XML_TAGS: &[(&str, Usage)] = &[
+    ("give_info", Usage::Set),
+    ("disable_info", Usage::Clear),
+    ("has_info", Usage::Test { inverted: false }),
+    ("dont_has_info", Usage::Test { inverted: true }),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Usage {
+    /// `+x` in effects, `<give_info>`.
+    Set,
+    /// `-x` in effects, `<disable_info>`.
+    Clear,
+    /// `+x`/`-x` in conditions, `<has_info>`/`<dont_has_info>`.
+    Test { inverted: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub file: PathBuf,
+    /// Position of the key in the file.
+    pub span: Slice,
+    pub usage: Usage,
+}
+
+/// Where every info portion of a tree is given, disabled and checked.
+#[derive(Debug, Default, PartialEq)]
+pub struct InfoIndex {
+    infos: BTreeMap<String, Vec<Reference>>,
+    /// Keys referenced by each file, so that updating a file only looks at
+    /// them. May hold keys the file no longer references.
+    files: BTreeMap<PathBuf, BTreeSet<String>>,
+}
+
+impl InfoIndex {
+    pub fn from_tree(tree: &Tree) -> Self {
+        let mut index = Self::default();
+        for file in tree.files() {
+            index.add_file(file);
+        }
+        index
+    }
+
+    /// Indexes an LTX or XML file, ignoring other files.
+    pub fn add_file(&mut self, file: &SourceFile) {
+        if file.is_ltx() {
+            // Broken files are reported by the linter, there is nothing to index.
+            if let Ok(ltx) = Ltx::from(&file.text) {
+                self.add_ltx(&file.path, &ltx);
+            }
+        } else if file.is_xml() {
+            self.add_xml(&file.path, &file.text);
+        }
+    }
+
+    /// Indexes every entry value of `ltx` that parses as a condlist.
+    pub fn add_ltx(&mut self, file: &Path, ltx: &Ltx) {
+        for condlist in ltx.condlists() {
+            if let Ok(ast) = ltx.parse(&condlist) {
+                self.add_ast(file, &ast, condlist.value.index());
+            }
+        }
+    }
+
+    /// Indexes a condlist located at `offset` in `file`.
+    pub fn add_ast(&mut self, file: &Path, ast: &Ast, offset: usize) {
+        AstIndexer {
+            index: self,
+            file,
+            offset,
+        }
+        .visit_ast(ast);
+    }
+
+    /// Forgets every reference in `file`, before indexing it again.
+    pub fn remove_file(&mut self, file: &Path) {
+        self.update_file(file, |_| None);
+    }
+
+    /// Follows an edit of `file` replacing `span` with `len` bytes: references
+    /// in `dropped`, positions from before the edit, are forgotten and those
+    /// after `span` are moved. The values replacing `dropped` are indexed
+    /// with [`InfoIndex::add_ast`].
+    pub fn edit_file(&mut self, file: &Path, span: Slice, len: usize, dropped: &[Slice]) {
+        self.update_file(file, |x| {
+            if dropped
+                .iter()
+                .any(|d| d.index() <= x.index() && x.end() <= d.end())
+            {
+                None
+            } else if x.index() >= span.end() {
+                Some(Slice::new(
+                    x.index() - span.end() + span.index() + len,
+                    x.len(),
+                ))
+            } else {
+                Some(x)
+            }
+        });
+    }
+
+    /// Moves the references in `file` to what `f` returns, forgetting them if
+    /// it returns `None`.
+    fn update_file(&mut self, file: &Path, mut f: impl FnMut(Slice) -> Option<Slice>) {
+        let Some(keys) = self.files.get_mut(file) else {
+            return;
+        };
+        keys.retain(|key| {
+            let Some(references) = self.infos.get_mut(key) else {
+                return false;
+            };
+            let mut found = false;
+            references.retain_mut(|x| {
+                if x.file != file {
+                    return true;
+                }
+                x.span = match f(x.span) {
+                    Some(span) => span,
+                    None => return false,
+                };
+                found = true;
+                true
+            });
+            if references.is_empty() {
+                self.infos.remove(key);
+            }
+            found
+        });
+        if keys.is_empty() {
+            self.files.remove(file);
+        }
+    }
+
+    pub fn add_xml(&mut self, file: &Path, text: &str) {
+        for (tag, usage) in XML_TAGS {
+            let open = format!("<{}>", tag);
+            let close = format!("</{}>", tag);
+            let mut from = 0;
+            while let Some(start) = text[from..].find(&open).map(|x| x + from + open.len()) {
+                let Some(end) = text[start..].find(&close).map(|x| x + start) else {
+                    break;
+                };
+                let inner = &text[start..end];
+                let key = inner.trim();
+                if !key.is_empty() {
+                    let ix = start + inner.len() - inner.trim_start().len();
+                    self.add(key, file, Slice::new(ix, key.len()), *usage);
+                }
+                from = end + close.len();
+            }
+        }
+    }
+
+    fn add(&mut self, key: &str, file: &Path, span: Slice, usage: Usage) {
+        self.files
+            .entry(file.to_owned())
+            .or_default()
+            .insert(key.to_owned());
+        self.infos
+            .entry(key.to_owned())
+            .or_default()
+            .push(Reference {
+                file: file.to_owned(),
+                span,
+                usage,
+            });
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.infos.keys().map(|x| x.as_str())
+    }
+
+    pub fn references(&self, key: &str) -> &[Reference] {
+        self.infos.get(key).map(|x| x.as_slice()).unwrap_or(&[])
+    }
+
+    /// Places that give `key`.
+    pub fn givers(&self, key: &str) -> impl Iterator<Item = &Reference> {
+        self.references(key)
+            .iter()
+            .filter(|x| x.usage == Usage::Set)
+    }
+
+    /// Places that check `key`.
+    pub fn readers(&self, key: &str) -> impl Iterator<Item = &Reference> {
+        self.references(key)
+            .iter()
+            .filter(|x| matches!(x.usage, Usage::Test { .. }))
+    }
+
+    /// Warns about info portions that are read but never given, and given but
+    /// never read.
+    pub fn check(&self) -> Vec<(PathBuf, Diagnostic)> {
+        let mut out = Vec::new();
+        for key in self.keys() {
+            let given = self.givers(key).next().is_some();
+            let read = self.readers(key).next().is_some();
+            if !given {
+                for x in self.readers(key) {
+                    let message = format!("Info portion `{}` is never given", key);
+                    let diagnostic = Diagnostic::warning(x.span, "info-never-given", message);
+                    out.push((x.file.clone(), diagnostic));
+                }
+            }
+            if !read {
+                for x in self.givers(key) {
+                    let message = format!("Info portion `{}` is never checked", key);
+                    let diagnostic = Diagnostic::warning(x.span, "info-never-read", message);
+                    out.push((x.file.clone(), diagnostic));
+                }
+            }
+        }
+        out
+    }
+}
+
+struct AstIndexer<'i> {
+    index: &'i mut InfoIndex,
+    file: &'i Path,
+    offset: usize,
+}
+
+impl Visit for AstIndexer<'_> {
+    fn visit_block(&mut self, ast: &Ast, block: &Block, cx: Context) {
+        let Block::InfoPortion { key, inverted } = block else {
+            return;
+        };
+        let usage = match (cx.position, inverted) {
+            (Position::Condition, _) => Usage::Test {
+                inverted: *inverted,
+            },
+            (Position::Effect, false) => Usage::Set,
+            (Position::Effect, true) => Usage::Clear,
+        };
+        self.index.add(
+            ast.slice_as_str(key),
+            self.file,
+            key.shifted(self.offset),
+            usage,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOGIC: &str = "\
+[logic]
+active = walker@1
+
+[walker@1]
+on_info = {+mar_q1_start -mar_q1_done} walker@2 %+mar_q1_done -mar_q1_start%
+meet = {+unknown} meet@1, meet@2
+";
+
+    const DIALOG: &str = "\
+<dialog id=\"mar_q1\">
+    <has_info>mar_q1_done</has_info>
+    <give_info> mar_q1_start </give_info>
+    <give_info>mar_q1_reward</give_info>
+</dialog>
+";
+
+    fn index() -> InfoIndex {
+        let mut index = InfoIndex::default();
+        index.add_ltx(Path::new("logic.ltx"), &Ltx::from(LOGIC).unwrap());
+        index.add_xml(Path::new("dialog.xml"), DIALOG);
+        index
+    }
+
+    #[test]
+    fn givers_and_readers() {
+        let index = index();
+        let givers = index.givers("mar_q1_done").collect::<Vec<_>>();
+        assert_eq!(givers.len(), 1);
+        assert_eq!(givers[0].file, Path::new("logic.ltx"));
+        assert_eq!(
+            &LOGIC[givers[0].span.index()..givers[0].span.end()],
+            "mar_q1_done"
+        );
+
+        let readers = index.readers("mar_q1_done").collect::<Vec<_>>();
+        assert_eq!(readers.len(), 2);
+        assert_eq!(readers[0].usage, Usage::Test { inverted: true });
+        assert_eq!(readers[1].file, Path::new("dialog.xml"));
+    }
+
+    #[test]
+    fn xml_span() {
+        let index = index();
+        let reference = &index.givers("mar_q1_start").next().unwrap();
+        assert_eq!(reference.file, Path::new("dialog.xml"));
+        assert_eq!(
+            &DIALOG[reference.span.index()..reference.span.end()],
+            "mar_q1_start"
+        );
+    }
+
+    #[test]
+    fn check() {
+        let index = index();
+        let codes = index
+            .check()
+            .into_iter()
+            .map(|(file, x)| (file, x.code))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            vec![
+                (PathBuf::from("dialog.xml"), "info-never-read"),
+                (PathBuf::from("logic.ltx"), "info-never-given"),
+            ]
+        );
+    }
+}
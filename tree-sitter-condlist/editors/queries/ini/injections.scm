@@ -0,0 +1,10 @@
+; extends
+; Condlists in LTX files opened with the `ini` grammar: the values of `active`,
+; `on_*` and `*_cond` keys. `on_timer = 1000 | walker@2` also includes the
+; parameter, which shows as an error.
+
+((setting
+  (setting_name) @_key
+  (setting_value) @injection.content)
+  (#match? @_key "^[ \t]*(active|on_[a-z0-9_]+|[a-z0-9_]+_cond)[ \t]*$")
+  (#set! injection.language "condlist"))
@@ -0,0 +1,68 @@
+/**
+ * Condlists as read by `Ast::from` in `src/parser.rs`. Both are checked
+ * against `test/corpus` by `cargo test`, which also runs `tree-sitter test`
+ * here when the CLI is installed. Change them together.
+ */
+
+// Characters `Parser::eat` gives a meaning to; everything else is a name.
+const NAME = /[^ \t\r\n{}%,+\-~=!]+/;
+
+module.exports = grammar({
+  name: 'condlist',
+
+  extras: $ => [/\s/],
+
+  rules: {
+    condlist: $ => seq(
+      optional($.statement),
+      repeat(seq(',', optional($.statement))),
+    ),
+
+    // `Ast::from` also takes the parts in any order and merges repeated
+    // conditions or effects; only the usual order is accepted here.
+    statement: $ => choice(
+      seq($.condition, optional($.output), optional($.effects)),
+      seq($.output, optional($.effects)),
+      $.effects,
+    ),
+
+    condition: $ => seq('{', repeat($._block), '}'),
+
+    effects: $ => seq('%', repeat($._block), '%'),
+
+    output: $ => NAME,
+
+    _block: $ => choice(
+      $.info_set,
+      $.info_clear,
+      $.call,
+      $.negated_call,
+      $.chance,
+    ),
+
+    info_set: $ => seq('+', $.name),
+
+    info_clear: $ => seq('-', $.name),
+
+    call: $ => seq('=', $.function, optional($.arguments)),
+
+    negated_call: $ => seq('!', $.function, optional($.arguments)),
+
+    arguments: $ => seq(
+      token.immediate('('),
+      optional($.argument),
+      repeat(seq(':', optional($.argument))),
+      ')',
+    ),
+
+    chance: $ => seq('~', $.number),
+
+    name: $ => token.immediate(NAME),
+
+    function: $ => token.immediate(/[^ \t\r\n{}%,+\-~=!(]+/),
+
+    argument: $ => /[^ \t\r\n{}%,+\-~=!():]+/,
+
+    number: $ => token.immediate(/[0-9]+/),
+  },
+});
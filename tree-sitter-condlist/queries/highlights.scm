@@ -0,0 +1,19 @@
+; Captures shared by Neovim and Helix.
+
+(info_set (name) @variable)
+(info_clear (name) @variable)
+
+(call (function) @function.call)
+(negated_call (function) @function.call)
+(argument) @variable.parameter
+
+(number) @number
+
+; The section switched to.
+(output) @label
+
+["+" "-" "=" "!" "~"] @operator
+
+["{" "}" "%" "(" ")"] @punctuation.bracket
+
+["," ":"] @punctuation.delimiter
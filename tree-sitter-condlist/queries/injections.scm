@@ -0,0 +1 @@
+; Condlists contain no other language.